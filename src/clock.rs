@@ -0,0 +1,155 @@
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+
+/// Time source of the dispatcher. All delays, timestamps and the sleep
+/// between two evaluation passes go through the clock of the environment.
+pub trait Clock: Send + Sync + Debug {
+    /// Seconds since the unix epoch.
+    fn now(&self) -> f64;
+    fn sleep(&self, duration: Duration);
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> f64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs_f64()
+    }
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+/// Virtual clock which only moves when told to. Sleeping on it advances
+/// the time instantly. Clones share the same time.
+#[derive(Debug, Clone)]
+pub struct ManualClock {
+    now: Arc<Mutex<f64>>,
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ManualClock {
+    /// Starts at the current system time.
+    pub fn new() -> ManualClock {
+        ManualClock::starting_at(SystemClock.now())
+    }
+    pub fn starting_at(now: f64) -> ManualClock {
+        ManualClock { now: Arc::new(Mutex::new(now)) }
+    }
+    pub fn advance(&self, seconds: f64) {
+        *self.now.lock().unwrap_or_else(|e| e.into_inner()) += seconds;
+    }
+    pub fn set(&self, now: f64) {
+        *self.now.lock().unwrap_or_else(|e| e.into_inner()) = now;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> f64 {
+        *self.now.lock().unwrap_or_else(|e| e.into_inner())
+    }
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration.as_secs_f64());
+    }
+}
+
+pub fn default_clock() -> Arc<dyn Clock> {
+    Arc::new(SystemClock)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Weekday {
+    Mon,
+    Tue,
+    Wed,
+    Thu,
+    Fri,
+    Sat,
+    Sun,
+}
+
+impl Weekday {
+    pub const WEEKDAYS: [Weekday; 5] = [Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu, Weekday::Fri];
+    pub const WEEKEND: [Weekday; 2] = [Weekday::Sat, Weekday::Sun];
+    pub const ALL: [Weekday; 7] = [Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu, Weekday::Fri, Weekday::Sat, Weekday::Sun];
+
+    /// 0 for Monday up to 6 for Sunday.
+    pub fn index(&self) -> usize {
+        *self as usize
+    }
+    pub fn previous(&self) -> Weekday {
+        Weekday::ALL[(self.index() + 6) % 7]
+    }
+}
+
+/// A timestamp broken down in the local timezone of the system.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct LocalTime {
+    pub year: i32,
+    pub month: u32, // 1 - 12
+    pub day: u32, // 1 - 31
+    pub weekday: Weekday,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+}
+
+impl LocalTime {
+    /// `unix` in seconds since the epoch, as returned by `Clock::now`.
+    pub fn from_unix(unix: f64) -> LocalTime {
+        let time = unix.floor() as libc::time_t;
+        let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+        // localtime_r only fails for years which do not fit an int
+        unsafe { libc::localtime_r(&time, &mut tm) };
+        LocalTime {
+            year: tm.tm_year + 1900,
+            month: tm.tm_mon as u32 + 1,
+            day: tm.tm_mday as u32,
+            weekday: Weekday::ALL[(tm.tm_wday as usize + 6) % 7], // tm_wday counts from Sunday
+            hour: tm.tm_hour as u32,
+            minute: tm.tm_min as u32,
+            second: tm.tm_sec as u32,
+        }
+    }
+
+    pub fn seconds_of_day(&self) -> u32 {
+        self.hour * 3600 + self.minute * 60 + self.second
+    }
+
+    /// Unix time at which the next local day starts. Days around DST changes
+    /// are shorter or longer than 86400 seconds.
+    pub(crate) fn next_midnight(&self) -> i64 {
+        let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+        tm.tm_year = self.year - 1900;
+        tm.tm_mon = self.month as i32 - 1;
+        tm.tm_mday = self.day as i32 + 1; // mktime normalizes the overflowing day
+        tm.tm_isdst = -1; // let mktime find out whether DST is in effect
+        unsafe { libc::mktime(&mut tm) as i64 }
+    }
+}
+
+/// Runs `f` with the local timezone set to `timezone`, a POSIX `TZ` value.
+/// Tests depending on the local time share the process environment and
+/// must go through this.
+#[cfg(test)]
+pub(crate) fn with_timezone<T>(timezone: &str, f: impl FnOnce() -> T) -> T {
+    use std::sync::Mutex;
+    static TIMEZONE: Mutex<()> = Mutex::new(());
+    extern "C" {
+        fn tzset();
+    }
+    let _guard = TIMEZONE.lock().unwrap_or_else(|e| e.into_inner());
+    std::env::set_var("TZ", timezone);
+    // localtime_r does not pick up a changed TZ by itself
+    unsafe { tzset() };
+    f()
+}
@@ -16,12 +16,224 @@ impl AppCondition {
 impl Condition for AppCondition {
     fn eval(&self, environment: &Environment, _: &RunningTreeState) -> Result<bool, TaskError> {
         match environment.app_state.get(self.key) {
+            // The key may hold floats if another condition compares it as Float
+            Some(state_value) if state_value.is_number() && self.value.is_number() => Ok(state_value.as_float() == self.value.as_float()),
             Some(state_value) => Ok(state_value == &self.value),
             None => Ok(false),
         }
     }
-    fn as_automaticlt_initializable(&self) -> Option<Vec<AutomaticltInitializable>> {
+    fn as_automaticlt_initializable(&self) -> Option<Vec<AutomaticltInitializable<'_>>> {
         Some(vec![AutomaticltInitializable::AppCondition(self)])
     }
     
 }
+
+/// Right hand side of an `AppComparison`: a constant or another app_state key.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Operand {
+    Value(f64),
+    Key(&'static str),
+}
+
+impl Operand {
+    fn resolve(&self, environment: &Environment) -> Option<f64> {
+        match self {
+            Operand::Value(value) => Some(*value),
+            Operand::Key(key) => numeric(environment, key),
+        }
+    }
+}
+
+impl From<f64> for Operand {
+    fn from(value: f64) -> Self {
+        Operand::Value(value)
+    }
+}
+impl From<i64> for Operand {
+    fn from(value: i64) -> Self {
+        Operand::Value(value as f64)
+    }
+}
+impl From<&'static str> for Operand {
+    fn from(key: &'static str) -> Self {
+        Operand::Key(key)
+    }
+}
+
+// Missing keys and None never compare
+fn numeric(environment: &Environment, key: &str) -> Option<f64> {
+    environment.app_state.get(key)
+        .filter(|value| !value.is_none())
+        .map(StateType::as_float)
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Comparison {
+    Greater(Operand),
+    AtLeast(Operand),
+    Less(Operand),
+    AtMost(Operand),
+    NotEqual(Operand),
+    InRange(Operand, Operand),
+}
+
+/// Numeric comparison of an app_state key against a constant or another key,
+/// e.g. `AppComparison::greater_than("temperature", 30.)` or
+/// `AppComparison::greater_than("temperature", "max_temperature")`.
+#[derive(Debug)]
+pub struct AppComparison {
+    pub(crate) key: &'static str,
+    comparison: Comparison,
+}
+
+impl AppComparison {
+    pub fn greater_than(key: &'static str, other: impl Into<Operand>) -> Box<Self> {
+        Box::new(AppComparison { key, comparison: Comparison::Greater(other.into()) })
+    }
+    pub fn at_least(key: &'static str, other: impl Into<Operand>) -> Box<Self> {
+        Box::new(AppComparison { key, comparison: Comparison::AtLeast(other.into()) })
+    }
+    pub fn less_than(key: &'static str, other: impl Into<Operand>) -> Box<Self> {
+        Box::new(AppComparison { key, comparison: Comparison::Less(other.into()) })
+    }
+    pub fn at_most(key: &'static str, other: impl Into<Operand>) -> Box<Self> {
+        Box::new(AppComparison { key, comparison: Comparison::AtMost(other.into()) })
+    }
+    pub fn not_equal(key: &'static str, other: impl Into<Operand>) -> Box<Self> {
+        Box::new(AppComparison { key, comparison: Comparison::NotEqual(other.into()) })
+    }
+    /// Inclusive on both ends.
+    pub fn in_range(key: &'static str, low: impl Into<Operand>, high: impl Into<Operand>) -> Box<Self> {
+        Box::new(AppComparison { key, comparison: Comparison::InRange(low.into(), high.into()) })
+    }
+}
+
+impl Condition for AppComparison {
+    fn eval(&self, environment: &Environment, _: &RunningTreeState) -> Result<bool, TaskError> {
+        let value = match numeric(environment, self.key) {
+            Some(value) => value,
+            None => return Ok(false),
+        };
+        let compare = |other: &Operand, compare: fn(f64, f64) -> bool| {
+            other.resolve(environment).is_some_and(|other| compare(value, other))
+        };
+        Ok(match &self.comparison {
+            Comparison::Greater(other) => compare(other, |a, b| a > b),
+            Comparison::AtLeast(other) => compare(other, |a, b| a >= b),
+            Comparison::Less(other) => compare(other, |a, b| a < b),
+            Comparison::AtMost(other) => compare(other, |a, b| a <= b),
+            Comparison::NotEqual(other) => compare(other, |a, b| a != b),
+            Comparison::InRange(low, high) => compare(low, |a, b| a >= b) && compare(high, |a, b| a <= b),
+        })
+    }
+}
+
+/// Turns true once a numeric app_state value rises above `upper` and only
+/// turns false again after it fell below `lower`, so a value hovering around
+/// one threshold does not toggle the condition on every evaluation.
+#[derive(Debug)]
+pub struct HysteresisCondition {
+    pub(crate) key: &'static str,
+    pub(crate) lower: Operand,
+    pub(crate) upper: Operand,
+    pub(crate) inverted: bool, // true below lower until above upper, e.g. for heating
+    pub(crate) use_flank: bool,
+    pub(crate) latched: Mutex<bool>,
+    pub(crate) has_flanked: Mutex<bool>,
+}
+
+impl HysteresisCondition {
+    pub fn new(key: &'static str, lower: impl Into<Operand>, upper: impl Into<Operand>) -> Box<Self> {
+        Box::new(HysteresisCondition {
+            key,
+            lower: lower.into(),
+            upper: upper.into(),
+            inverted: false,
+            use_flank: false,
+            latched: Mutex::new(false),
+            has_flanked: Mutex::new(false),
+        })
+    }
+    pub fn inverted(mut self: Box<Self>) -> Box<Self> {
+        self.inverted = true;
+        self
+    }
+    /// Only true on the evaluation at which the threshold was crossed.
+    pub fn on_flank(mut self: Box<Self>) -> Box<Self> {
+        self.use_flank = true;
+        self
+    }
+}
+
+impl Condition for HysteresisCondition {
+    fn eval(&self, environment: &Environment, _: &RunningTreeState) -> Result<bool, TaskError> {
+        let mut latched = self.latched
+            .try_lock()
+            .map_err(|_| TaskError::IoError { comment: "Failed to lock latched".to_string() })?;
+        let mut has_flanked = self.has_flanked
+            .try_lock()
+            .map_err(|_| TaskError::IoError { comment: "Failed to lock has_flanked".to_string() })?;
+
+        // A missing value or threshold keeps the latched state
+        if let (Some(value), Some(lower), Some(upper)) = (numeric(environment, self.key),
+                self.lower.resolve(environment), self.upper.resolve(environment)) {
+            if value > upper {
+                *latched = !self.inverted;
+            } else if value < lower {
+                *latched = self.inverted;
+            }
+        }
+        if !*latched {
+            *has_flanked = false;
+            return Ok(false);
+        }
+        if self.use_flank && *has_flanked {
+            return Ok(false);
+        }
+        *has_flanked = true;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use super::*;
+    use crate::testing::{counting_task, Simulation};
+
+    #[test]
+    fn hysteresis_latches_between_thresholds() {
+        let simulation = Simulation::new();
+        let (cooling, heating) = (Arc::new(AtomicU32::new(0)), Arc::new(AtomicU32::new(0)));
+        let mut suite = simulation.suite(vec![
+            ("cooling", vec![counting_task("cooling", &cooling)
+                .when_condition(HysteresisCondition::new("temperature", 20., 25.).on_flank())
+                .to_eveluatable()]),
+            ("heating", vec![counting_task("heating", &heating)
+                .when_condition(HysteresisCondition::new("temperature", 18., "heat_below").inverted().on_flank())
+                .to_eveluatable()]),
+        ]);
+        let environment = suite.environment();
+        environment.write().unwrap().app_state.set("heat_below", StateType::Float(22.)).unwrap();
+
+        for (second, temperature, cooled, heated) in [
+            (1, 22., 0, 0), // heating needs to fall below 18 first
+            (2, 25.5, 1, 0),
+            (3, 24., 1, 0), // still latched
+            (4, 26., 1, 0), // no new flank while latched
+            (5, 19., 1, 0), // below 20 releases cooling
+            (6, 26., 2, 0),
+            (7, 17.5, 2, 1),
+            (8, 21., 2, 1), // inverted stays latched up to 22
+            (9, 22.5, 2, 1),
+            (10, 17., 2, 2),
+        ] {
+            environment.write().unwrap().app_state.set("temperature", StateType::Float(temperature)).unwrap();
+            simulation.tick_at(&mut suite, second as f64);
+            assert_eq!(cooling.load(Ordering::SeqCst), cooled, "cooling at {}", temperature);
+            assert_eq!(heating.load(Ordering::SeqCst), heated, "heating at {}", temperature);
+        }
+    }
+}
@@ -8,7 +8,7 @@ pub struct AllwaysTrue{
     pub(crate) first_exec_at: Mutex<f64>,
 }
 impl Condition for AllwaysTrue {
-    fn eval(&self, _environment: &Environment, running_tree: &RunningTreeState) -> Result<bool, TaskError> {
+    fn eval(&self, environment: &Environment, running_tree: &RunningTreeState) -> Result<bool, TaskError> {
         let mut first_exec = self.first_exec_at
             .try_lock()
             .map_err(|_| TaskError::IoError { comment: "Failed to lock first_exec_at".to_string() })?;
@@ -17,11 +17,11 @@ impl Condition for AllwaysTrue {
             .map_err(|_| TaskError::IoError { comment: "Failed to lock has_flanked".to_string() })?;
         
         if running_tree.first_iteration_after_move {
-            *first_exec = unix_now!(f64);
+            *first_exec = environment.now();
             *has_flanked = false;
         }
 
-        if self.with_delay > 0. && unix_now!(f64) - self.with_delay < *first_exec {
+        if self.with_delay > 0. && environment.now() - self.with_delay < *first_exec {
             return Ok(false);
         }
         if self.with_flank && *has_flanked {
@@ -0,0 +1,303 @@
+use super::*;
+use crate::clock::LocalTime;
+
+/// What happens to runs whose time passed between two evaluations,
+/// e.g. while the task was still running or the system was suspended.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MissedRuns {
+    CatchUp, // fire once on the next evaluation, no matter how many were missed
+    Skip,
+}
+
+const MONTHS: [&str; 12] = ["JAN", "FEB", "MAR", "APR", "MAY", "JUN", "JUL", "AUG", "SEP", "OCT", "NOV", "DEC"];
+const WEEKDAYS: [&str; 7] = ["SUN", "MON", "TUE", "WED", "THU", "FRI", "SAT"];
+
+#[derive(Clone, Copy, Debug)]
+struct Field {
+    allowed: u64, // bit n set if value n matches
+    any: bool, // was `*`, matters for the day of month / day of week rule
+}
+
+impl Field {
+    fn parse(field: &str, min: u32, max: u32, names: &[&str]) -> Option<Field> {
+        let value = |value: &str| -> Option<u32> {
+            let value = match names.iter().position(|name| name.eq_ignore_ascii_case(value)) {
+                Some(index) => index as u32 + min,
+                None => value.parse().ok()?,
+            };
+            (min..=max).contains(&value).then_some(value)
+        };
+        let mut allowed = 0u64;
+        for part in field.split(',') {
+            let (range, step) = match part.split_once('/') {
+                Some((range, step)) => (range, step.parse::<u32>().ok().filter(|step| *step > 0)?),
+                None => (part, 1),
+            };
+            let (start, end) = match range {
+                "*" => (min, max),
+                range => match range.split_once('-') {
+                    Some((start, end)) => (value(start)?, value(end)?),
+                    // `5/15` runs from 5 to the end of the range
+                    None if step > 1 => (value(range)?, max),
+                    None => (value(range)?, value(range)?),
+                },
+            };
+            if start > end {
+                return None;
+            }
+            for n in (start..=end).step_by(step as usize) {
+                allowed |= 1 << n;
+            }
+        }
+        Some(Field { allowed, any: field == "*" })
+    }
+
+    fn matches(&self, value: u32) -> bool {
+        self.allowed & (1 << value) != 0
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Schedule {
+    seconds: Option<Field>, // None for five field expressions, which run at second 0
+    minutes: Field,
+    hours: Field,
+    days: Field,
+    months: Field,
+    weekdays: Field,
+}
+
+impl Schedule {
+    fn parse(expression: &str) -> Option<Schedule> {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let (seconds, rest) = match fields.len() {
+            5 => (None, &fields[..]),
+            6 => (Some(Field::parse(fields[0], 0, 59, &[])?), &fields[1..]),
+            _ => return None,
+        };
+        let mut weekdays = Field::parse(rest[4], 0, 7, &WEEKDAYS)?;
+        if weekdays.matches(7) {
+            weekdays.allowed |= 1; // 7 is sunday as well
+        }
+        Some(Schedule {
+            seconds,
+            minutes: Field::parse(rest[0], 0, 59, &[])?,
+            hours: Field::parse(rest[1], 0, 23, &[])?,
+            days: Field::parse(rest[2], 1, 31, &[])?,
+            months: Field::parse(rest[3], 1, 12, &MONTHS)?,
+            weekdays,
+        })
+    }
+
+    /// Seconds between two possible runs.
+    fn resolution(&self) -> i64 {
+        if self.seconds.is_some() { 1 } else { 60 }
+    }
+
+    fn day_matches(&self, time: &LocalTime) -> bool {
+        let weekday = (time.weekday.index() as u32 + 1) % 7; // cron counts from sunday
+        // Like cron: if both are restricted, either of them has to match
+        match (self.days.any, self.weekdays.any) {
+            (false, false) => self.days.matches(time.day) || self.weekdays.matches(weekday),
+            _ => self.days.matches(time.day) && self.weekdays.matches(weekday),
+        }
+    }
+
+    fn matches(&self, unix: i64) -> bool {
+        let time = LocalTime::from_unix(unix as f64);
+        self.seconds.is_none_or(|seconds| seconds.matches(time.second))
+            && self.minutes.matches(time.minute)
+            && self.hours.matches(time.hour)
+            && self.months.matches(time.month)
+            && self.day_matches(&time)
+    }
+
+    /// Whether any run falls into `after < time <= until`. Skips whole days,
+    /// hours and minutes which can not match instead of testing every second.
+    fn runs_between(&self, after: i64, until: i64) -> bool {
+        let resolution = self.resolution();
+        let mut time = (after / resolution + 1) * resolution;
+        while time <= until {
+            let local = LocalTime::from_unix(time as f64);
+            let into_hour = (local.minute * 60 + local.second) as i64;
+            time = if !self.months.matches(local.month) || !self.day_matches(&local) {
+                local.next_midnight().max(time + 1)
+            } else if !self.hours.matches(local.hour) {
+                time + 3600 - into_hour
+            } else if !self.minutes.matches(local.minute) {
+                time + 60 - local.second as i64
+            } else if self.seconds.is_none_or(|seconds| seconds.matches(local.second)) {
+                return true;
+            } else {
+                time + 1
+            };
+        }
+        false
+    }
+}
+
+/// Fires once for every time matching a cron expression in local time.
+/// Takes five fields (`minute hour day month weekday`) or six with seconds
+/// in front, each one `*`, a value, a range or a list of those, optionally
+/// with a `/step`. Months and weekdays also accept names like `JAN` and `MON`.
+#[derive(Debug)]
+pub struct CronCondition {
+    schedule: Result<Schedule, String>, // errors are reported when evaluated, the builders can not fail
+    missed_runs: MissedRuns,
+    last_evaluated: Mutex<Option<i64>>, // time slot of the last evaluation
+}
+
+impl CronCondition {
+    pub fn new(expression: &str) -> Box<Self> {
+        Box::new(CronCondition {
+            schedule: Schedule::parse(expression).ok_or(format!("Invalid cron expression: '{}'", expression)),
+            missed_runs: MissedRuns::CatchUp,
+            last_evaluated: Mutex::new(None),
+        })
+    }
+    pub fn missed_runs(mut self: Box<Self>, missed_runs: MissedRuns) -> Box<Self> {
+        self.missed_runs = missed_runs;
+        self
+    }
+}
+
+impl Condition for CronCondition {
+    fn eval(&self, environment: &Environment, _: &RunningTreeState) -> Result<bool, TaskError> {
+        let schedule = self.schedule.as_ref()
+            .map_err(|invalid| TaskError::TriggerError { comment: invalid.clone() })?;
+        let mut last_evaluated = self.last_evaluated
+            .try_lock()
+            .map_err(|_| TaskError::IoError { comment: "Failed to lock last_evaluated".to_string() })?;
+
+        let resolution = schedule.resolution();
+        let slot = (environment.now().floor() as i64).div_euclid(resolution) * resolution;
+        let fire = match *last_evaluated {
+            None => schedule.matches(slot),
+            // Already handled, or the clock went backwards
+            Some(last) if slot <= last => false,
+            Some(last) => schedule.matches(slot) || (self.missed_runs == MissedRuns::CatchUp
+                && schedule.runs_between(last, slot - resolution)),
+        };
+        *last_evaluated = Some(slot);
+        Ok(fire)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::with_timezone;
+
+    const BERLIN: &str = "CET-1CEST,M3.5.0,M10.5.0/3";
+
+    fn values(field: Field) -> Vec<u32> {
+        (0..64).filter(|n| field.matches(*n)).collect()
+    }
+
+    #[test]
+    fn parses_steps_ranges_and_lists() {
+        assert_eq!(values(Field::parse("5/15", 0, 59, &[]).unwrap()), vec![5, 20, 35, 50]);
+        assert_eq!(values(Field::parse("*/20", 0, 59, &[]).unwrap()), vec![0, 20, 40]);
+        assert_eq!(values(Field::parse("1-10/3", 0, 59, &[]).unwrap()), vec![1, 4, 7, 10]);
+        assert_eq!(values(Field::parse("1,3,5-6", 0, 59, &[]).unwrap()), vec![1, 3, 5, 6]);
+        assert!(Field::parse("*", 0, 59, &[]).unwrap().any);
+        assert!(!Field::parse("0-59", 0, 59, &[]).unwrap().any);
+    }
+
+    #[test]
+    fn rejects_invalid_fields() {
+        for invalid in ["60", "10-5", "*/0", "5/x", "", "1,,2", "-1"] {
+            assert!(Field::parse(invalid, 0, 59, &[]).is_none(), "{} should be invalid", invalid);
+        }
+        assert!(Schedule::parse("* * * *").is_none());
+        assert!(Schedule::parse("* * * * * * *").is_none());
+        assert!(Schedule::parse("* * 0 * *").is_none());
+    }
+
+    #[test]
+    fn parses_names() {
+        assert_eq!(values(Field::parse("jan,MAR-may", 1, 12, &MONTHS).unwrap()), vec![1, 3, 4, 5]);
+        let schedule = Schedule::parse("0 8 * * MON-FRI").unwrap();
+        assert_eq!(values(schedule.weekdays), vec![1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn weekday_seven_is_sunday() {
+        let schedule = Schedule::parse("0 0 * * 7").unwrap();
+        assert!(schedule.weekdays.matches(0));
+        assert_eq!(Schedule::parse("0 0 * * 5-7").unwrap().weekdays.allowed & 1, 1);
+    }
+
+    #[test]
+    fn day_of_month_or_day_of_week() {
+        with_timezone("UTC0", || {
+            let (thursday_13th, friday_14th, saturday_15th) = (1718280000, 1718366400, 1718452800);
+            // Both restricted: either one matches
+            let either = Schedule::parse("0 12 13 * FRI").unwrap();
+            assert!(either.matches(thursday_13th));
+            assert!(either.matches(friday_14th));
+            assert!(!either.matches(saturday_15th));
+            // Only one restricted: that one has to match
+            let day_only = Schedule::parse("0 12 13 * *").unwrap();
+            assert!(day_only.matches(thursday_13th));
+            assert!(!day_only.matches(friday_14th));
+            let weekday_only = Schedule::parse("0 12 * * FRI").unwrap();
+            assert!(!weekday_only.matches(thursday_13th));
+            assert!(weekday_only.matches(friday_14th));
+        });
+    }
+
+    #[test]
+    fn seconds_field() {
+        with_timezone("UTC0", || {
+            let schedule = Schedule::parse("*/10 * * * * *").unwrap();
+            assert_eq!(schedule.resolution(), 1);
+            assert!(schedule.matches(1718280010));
+            assert!(!schedule.matches(1718280011));
+            assert_eq!(Schedule::parse("* * * * *").unwrap().resolution(), 60);
+        });
+    }
+
+    #[test]
+    fn catches_up_across_days() {
+        with_timezone("UTC0", || {
+            let schedule = Schedule::parse("30 2 * * *").unwrap();
+            let (thursday_3am, friday_2am, friday_3am) = (1718247600, 1718330400, 1718334000);
+            assert!(!schedule.runs_between(thursday_3am, friday_2am));
+            assert!(schedule.runs_between(thursday_3am, friday_3am));
+            // The bounds are `after < time <= until`
+            assert!(!schedule.runs_between(friday_2am + 1800, friday_3am));
+            assert!(schedule.runs_between(friday_2am + 1799, friday_2am + 1800));
+            // Monthly runs are found across a long gap
+            let monthly = Schedule::parse("0 0 1 * *").unwrap();
+            assert!(monthly.runs_between(thursday_3am, thursday_3am + 20 * 86400));
+            assert!(!monthly.runs_between(thursday_3am, thursday_3am + 10 * 86400));
+        });
+    }
+
+    #[test]
+    fn catches_up_across_dst_changes() {
+        with_timezone(BERLIN, || {
+            let schedule = Schedule::parse("30 2 * * *").unwrap();
+            // 2024-03-31 has no 02:30, the clocks jump from 02:00 to 03:00
+            let (spring_midnight, spring_5am) = (1711839600, 1711854000);
+            assert!(!schedule.runs_between(spring_midnight, spring_5am));
+            assert!(schedule.runs_between(spring_midnight - 86400, spring_5am));
+            // 2024-10-27 has 02:30 twice, both are runs
+            let (autumn_midnight, first, second, autumn_5am) = (1729980000, 1729989000, 1729992600, 1730001600);
+            assert!(schedule.matches(first));
+            assert!(schedule.matches(second));
+            assert!(schedule.runs_between(autumn_midnight, autumn_5am));
+            assert!(!schedule.runs_between(second, autumn_5am));
+            // Hourly runs keep matching through the repeated hour
+            let hourly = Schedule::parse("0 * * * *").unwrap();
+            assert!(hourly.runs_between(first, second));
+            // The day after the 23 hour one is reached at its midnight
+            let weekly = Schedule::parse("30 0 * * MON").unwrap();
+            let (saturday_noon, monday_0030, monday_0130) = (1711796400, 1711924200, 1711927800);
+            assert!(weekly.matches(monday_0030));
+            assert!(weekly.runs_between(saturday_noon, monday_0130));
+            assert!(!weekly.runs_between(saturday_noon, monday_0030 - 60));
+        });
+    }
+}
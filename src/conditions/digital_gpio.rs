@@ -15,7 +15,7 @@ impl DigitalGpioCondition {
         Box::new(DigitalGpioCondition { pin, state: true, delay: 0., use_flank: false, is_output: true, has_flanked: Mutex::new(false), first_eval_at: Mutex::new(-1.) })
     }
     pub fn new_input(pin: u8) -> Box<Self> {
-        Box::new(DigitalGpioCondition { pin: pin, state: true, delay: 0., use_flank: false, is_output: false, has_flanked: Mutex::new(false), first_eval_at: Mutex::new(-1.) })
+        Box::new(DigitalGpioCondition { pin, state: true, delay: 0., use_flank: false, is_output: false, has_flanked: Mutex::new(false), first_eval_at: Mutex::new(-1.) })
     }
     pub fn when_false(mut self: Box<Self>) -> Box<Self> {
         self.state = false;
@@ -44,22 +44,22 @@ impl Condition for DigitalGpioCondition {
         };
         let mut first_eval_at = self.first_eval_at.try_lock();
         if running_tree.first_iteration_after_move {
-            self.has_flanked.try_lock().and_then(|mut lock| Ok((*lock) = false)).ok();
+            self.has_flanked.try_lock().map(|mut lock| *lock = false).ok();
             if !running_tree.currently_active || running_tree.moved_in_from_back 
                 || first_eval_at.as_ref().map(|val| **val).unwrap_or(-1.) == -1. {
                 first_eval_at.as_mut()
-                    .and_then(|lock| Ok((**lock) = unix_now!(f64))).ok();
+                    .map(|lock| **lock = environment.now()).ok();
              }
         }
         //let most_recent_change = first_eval_at.map(|val| *val).unwrap_or_else(|_| main_condition_handler.last_change());
         let most_recent_change = first_eval_at.map(|val| *val)
             .unwrap_or(-1.)
             .max(main_condition_handler.last_change());
-        let a = unix_now!(f64) - most_recent_change;
+        let a = environment.now() - most_recent_change;
         if main_condition_handler.current_state() != self.state || 
             a < self.delay {
 
-            let _ = self.has_flanked.try_lock().and_then(|mut lock| Ok((*lock) = false));
+            let _ = self.has_flanked.try_lock().map(|mut lock| *lock = false);
             Ok(false)
         }
         else {
@@ -74,7 +74,7 @@ impl Condition for DigitalGpioCondition {
             Ok(true)
         }
     }
-    fn as_automaticlt_initializable(&self) -> Option<Vec<AutomaticltInitializable>> {
+    fn as_automaticlt_initializable(&self) -> Option<Vec<AutomaticltInitializable<'_>>> {
         Some(vec![AutomaticltInitializable::DigitalGpioCondition(self)])
     }
 }
@@ -15,12 +15,6 @@ impl TreeCondition {
 
 impl Condition for TreeCondition {
     fn eval(&self, _: &Environment, running_tree: &RunningTreeState) -> Result<bool, TaskError> {
-        if running_tree.moved_in_from_back && !self.when_moving_up {
-            Ok(true)
-        } else if !running_tree.moved_in_from_back && self.when_moving_up {
-            Ok(true)
-        } else {
-            Ok(false)
-        }
+        Ok(running_tree.moved_in_from_back != self.when_moving_up)
     }
 }
\ No newline at end of file
@@ -0,0 +1,117 @@
+use super::*;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EdgeKind {
+    Rising,
+    Falling,
+    Any,
+}
+
+/// True in exactly the tick in which a pin changed its level, and only
+/// once within that tick. Unlike `DigitalGpioCondition::on_flank` it does
+/// not fire for a level which was already set when the unit was entered.
+/// Outputs are sampled once per tick, so an output switched on and off
+/// again between two ticks does not produce an edge.
+#[derive(Debug)]
+pub struct EdgeCondition {
+    pub(crate) pin: u8,
+    pub(crate) is_output: bool,
+    pub(crate) kind: EdgeKind,
+    fired_in_tick: Mutex<u64>,
+}
+
+impl EdgeCondition {
+    fn new(pin: u8, is_output: bool, kind: EdgeKind) -> Box<Self> {
+        Box::new(EdgeCondition { pin, is_output, kind, fired_in_tick: Mutex::new(0) })
+    }
+    pub fn rising_edge(pin: u8) -> Box<Self> {
+        EdgeCondition::new(pin, false, EdgeKind::Rising)
+    }
+    pub fn falling_edge(pin: u8) -> Box<Self> {
+        EdgeCondition::new(pin, false, EdgeKind::Falling)
+    }
+    pub fn any_edge(pin: u8) -> Box<Self> {
+        EdgeCondition::new(pin, false, EdgeKind::Any)
+    }
+    /// Watches the output pin instead of the input pin.
+    pub fn of_output(mut self: Box<Self>) -> Box<Self> {
+        self.is_output = true;
+        self
+    }
+}
+
+impl Condition for EdgeCondition {
+    fn eval(&self, environment: &Environment, _: &RunningTreeState) -> Result<bool, TaskError> {
+        let handler: &dyn PinHandler = match self.is_output {
+            true => environment.output_gpios.get(&self.pin)
+                .ok_or(TaskError::IoError { comment: format!("Pin {} not found in output GPIO state", self.pin) })?,
+            false => environment.input_gpios.get(&self.pin)
+                .ok_or(TaskError::IoError { comment: format!("Pin {} not found in input GPIO state", self.pin) })?,
+        };
+        let mut fired_in_tick = self.fired_in_tick
+            .try_lock()
+            .map_err(|_| TaskError::IoError { comment: "Failed to lock fired_in_tick".to_string() })?;
+
+        let tick = environment.current_tick();
+        if handler.changed_in_tick() != tick || *fired_in_tick == tick {
+            return Ok(false);
+        }
+        let fire = match self.kind {
+            EdgeKind::Rising => handler.current_state(),
+            EdgeKind::Falling => !handler.current_state(),
+            EdgeKind::Any => true,
+        };
+        if fire {
+            *fired_in_tick = tick;
+        }
+        Ok(fire)
+    }
+    fn as_automaticlt_initializable(&self) -> Option<Vec<AutomaticltInitializable<'_>>> {
+        Some(vec![match self.is_output {
+            true => AutomaticltInitializable::OutputPin(self.pin),
+            false => AutomaticltInitializable::InputPin(self.pin),
+        }])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use super::*;
+    use crate::conditions::digital_gpio::DigitalGpioCondition;
+    use crate::tasks::task_context::Unit;
+    use crate::testing::{counting_task, Simulation};
+
+    #[test]
+    fn edges_fire_in_a_single_tick() {
+        let simulation = Simulation::new();
+        // Active low, two presses
+        simulation.gpio.load_script("pin 17 low at 1, high at 2, low at 3, high at 4").unwrap();
+        let (rising, falling, late) = (Arc::new(AtomicU32::new(0)), Arc::new(AtomicU32::new(0)), Arc::new(AtomicU32::new(0)));
+        let mut suite = simulation.suite(vec![
+            ("edges", vec![
+                counting_task("rising", &rising).when_condition(EdgeCondition::rising_edge(17)).to_eveluatable(),
+                counting_task("falling", &falling).when_condition(EdgeCondition::falling_edge(17)).to_eveluatable(),
+            ]),
+            // Entered while the button is already pressed, so there is no edge inside
+            ("held", vec![Unit::new("held")
+                .when_condition(DigitalGpioCondition::new_input(17).after_delay(0.5))
+                .stay_while_condition(DigitalGpioCondition::new_input(17))
+                .subunit(counting_task("late", &late).when_condition(EdgeCondition::rising_edge(17)).to_eveluatable())
+                .to_eveluatable()]),
+        ]);
+        let counts = || (rising.load(Ordering::SeqCst), falling.load(Ordering::SeqCst), late.load(Ordering::SeqCst));
+
+        simulation.run_to(&mut suite, 1., 0.25);
+        assert_eq!(counts(), (1, 0, 0));
+        simulation.run_to(&mut suite, 1.75, 0.25);
+        assert_eq!(suite.active_unit("held").as_deref(), Some("held"));
+        assert_eq!(counts(), (1, 0, 0));
+        simulation.run_to(&mut suite, 2., 0.25);
+        assert_eq!(counts(), (1, 1, 0));
+        simulation.run_to(&mut suite, 5., 0.25);
+        assert_eq!(counts(), (2, 2, 0));
+    }
+}
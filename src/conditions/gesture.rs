@@ -0,0 +1,198 @@
+use super::*;
+use crate::types::{Edge, InputPinHandler};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Gesture {
+    LongPress(f64),
+    Clicks(usize),
+}
+
+/// Button gestures on an input pin, based on the edges of its debounced
+/// level. Every gesture fires once:
+/// * `long_press` while the button is still held, once it was held for the given time,
+/// * `clicks` once exactly that many short presses (at most `max_press` seconds),
+///   each starting within `within` seconds of the last release, are followed
+///   by `within` seconds without another press. `short_press` and
+///   `double_click` are one and two clicks.
+///
+/// The gestures exclude each other, so a short press, a double click and a
+/// long press can trigger different tasks on the same button.
+///
+/// Gestures which happened before the unit of the condition was entered,
+/// or before it was first evaluated, are ignored.
+#[derive(Debug)]
+pub struct GestureCondition {
+    pub(crate) pin: u8,
+    gesture: Gesture,
+    pub(crate) max_press: f64,
+    pub(crate) within: f64,
+    handled: Mutex<Option<u64>>, // id of the last edge a gesture was consumed at
+}
+
+impl GestureCondition {
+    fn new(pin: u8, gesture: Gesture) -> Box<Self> {
+        Box::new(GestureCondition { pin, gesture, max_press: 0.5, within: 0.4, handled: Mutex::new(None) })
+    }
+    /// A single click, fires only after `within` seconds passed without a second one.
+    pub fn short_press(pin: u8) -> Box<Self> {
+        GestureCondition::new(pin, Gesture::Clicks(1))
+    }
+    pub fn long_press(pin: u8, held_for: f64) -> Box<Self> {
+        GestureCondition::new(pin, Gesture::LongPress(held_for))
+    }
+    pub fn double_click(pin: u8) -> Box<Self> {
+        GestureCondition::new(pin, Gesture::Clicks(2))
+    }
+    pub fn clicks(pin: u8, count: usize) -> Box<Self> {
+        GestureCondition::new(pin, Gesture::Clicks(count))
+    }
+    /// Longest press which still counts as short press or click, 0.5s by default.
+    pub fn max_press(mut self: Box<Self>, seconds: f64) -> Box<Self> {
+        self.max_press = seconds;
+        self
+    }
+    /// Longest pause between the clicks of a sequence, 0.4s by default.
+    pub fn within(mut self: Box<Self>, seconds: f64) -> Box<Self> {
+        self.within = seconds;
+        self
+    }
+
+    /// Short presses in a row ending with `edges[last]`, a release.
+    fn count_clicks(&self, edges: &[&Edge]) -> usize {
+        let mut clicks = 0;
+        let mut index = edges.len();
+        // Walk back over (press, release) pairs
+        while index >= 2 {
+            let (press, release) = (edges[index - 2], edges[index - 1]);
+            if !press.state || release.state || release.at - press.at > self.max_press {
+                break;
+            }
+            clicks += 1;
+            index -= 2;
+            match index.checked_sub(1).map(|before| edges[before]) {
+                Some(previous_release) if press.at - previous_release.at <= self.within => {},
+                _ => break,
+            }
+        }
+        clicks
+    }
+
+    fn detect(&self, handler: &InputPinHandler, now: f64) -> Option<u64> {
+        let edges: Vec<&Edge> = handler.edges().collect();
+        let last = *edges.last()?;
+        match self.gesture {
+            Gesture::LongPress(held_for) => (last.state && now - last.at >= held_for).then_some(last.id),
+            Gesture::Clicks(count) => {
+                if last.state || now - last.at <= self.within {
+                    return None;
+                }
+                // The sequence is consumed even if the count does not match
+                Some(if self.count_clicks(&edges) == count { last.id } else { 0 })
+            },
+        }
+    }
+}
+
+impl Condition for GestureCondition {
+    fn eval(&self, environment: &Environment, running_tree: &RunningTreeState) -> Result<bool, TaskError> {
+        let handler = environment.input_gpios.get(&self.pin)
+            .ok_or(TaskError::IoError { comment: format!("Pin {} not found in input GPIO state", self.pin) })?;
+        let mut handled = self.handled
+            .try_lock()
+            .map_err(|_| TaskError::IoError { comment: "Failed to lock handled".to_string() })?;
+        let latest = handler.edges().next_back().map_or(0, |edge| edge.id);
+        // Like a flank, a gesture made before its unit was entered does not count
+        if running_tree.first_iteration_after_move {
+            *handled = Some(latest);
+        }
+        let handled = handled.get_or_insert(latest);
+
+        match self.detect(handler, environment.now()) {
+            Some(0) => {
+                *handled = latest;
+                Ok(false)
+            },
+            Some(id) if id > *handled => {
+                *handled = id;
+                Ok(true)
+            },
+            _ => Ok(false),
+        }
+    }
+    fn as_automaticlt_initializable(&self) -> Option<Vec<AutomaticltInitializable<'_>>> {
+        Some(vec![AutomaticltInitializable::InputPin(self.pin)])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use super::*;
+    use crate::conditions::logic_gates::Gates;
+    use crate::tasks::task_context::Unit;
+    use crate::testing::{counting_task, Simulation};
+
+    #[test]
+    fn gestures_on_one_button_exclude_each_other() {
+        let simulation = Simulation::new();
+        // Active low: a short press, a double click and a two second press
+        simulation.gpio.load_script("pin 17 low at 1, high at 1.2, \
+            low at 3, high at 3.1, low at 3.3, high at 3.4, \
+            low at 5, high at 7").unwrap();
+        let (short, double, long) = (Arc::new(AtomicU32::new(0)), Arc::new(AtomicU32::new(0)), Arc::new(AtomicU32::new(0)));
+        let mut suite = simulation.suite(vec![("button", vec![
+            counting_task("short", &short).when_condition(GestureCondition::short_press(17)).to_eveluatable(),
+            counting_task("double", &double).when_condition(GestureCondition::double_click(17)).to_eveluatable(),
+            counting_task("long", &long).when_condition(GestureCondition::long_press(17, 1.)).to_eveluatable(),
+        ])]);
+        let counts = || (short.load(Ordering::SeqCst), double.load(Ordering::SeqCst), long.load(Ordering::SeqCst));
+
+        // A click only counts once no second one followed
+        simulation.run_to(&mut suite, 1.5, 0.05);
+        assert_eq!(counts(), (0, 0, 0));
+        simulation.run_to(&mut suite, 2.5, 0.05);
+        assert_eq!(counts(), (1, 0, 0));
+        simulation.run_to(&mut suite, 4.5, 0.05);
+        assert_eq!(counts(), (1, 1, 0));
+        // A long press fires while still held and is no click when released
+        simulation.run_to(&mut suite, 6.5, 0.05);
+        assert_eq!(counts(), (1, 1, 1));
+        simulation.run_to(&mut suite, 9., 0.05);
+        assert_eq!(counts(), (1, 1, 1));
+    }
+
+    #[test]
+    fn gestures_inside_a_unit_start_when_it_is_entered() {
+        let simulation = Simulation::new();
+        // Double clicks open the menu and confirm inside it, a long press closes it
+        simulation.gpio.load_script("pin 17 low at 1, high at 1.1, low at 1.3, high at 1.4, \
+            low at 3, high at 3.1, low at 3.3, high at 3.4, \
+            low at 5, high at 7, \
+            low at 8, high at 8.1, low at 8.3, high at 8.4, \
+            low at 10, high at 10.1, low at 10.3, high at 10.4").unwrap();
+        let confirmed = Arc::new(AtomicU32::new(0));
+        let mut suite = simulation.suite(vec![("menu", vec![Unit::new("menu")
+            .when_condition(GestureCondition::double_click(17))
+            .stay_while_condition(Gates::not().condition(GestureCondition::long_press(17, 1.)))
+            .subunit(counting_task("confirm", &confirmed).when_condition(GestureCondition::double_click(17)).to_eveluatable())
+            .to_eveluatable()])]);
+
+        // The double click which opened the menu does not confirm
+        simulation.run_to(&mut suite, 2.5, 0.05);
+        assert_eq!(suite.active_unit("menu").as_deref(), Some("menu"));
+        assert_eq!(confirmed.load(Ordering::SeqCst), 0);
+        simulation.run_to(&mut suite, 4.5, 0.05);
+        assert_eq!(confirmed.load(Ordering::SeqCst), 1);
+        simulation.run_to(&mut suite, 7.5, 0.05);
+        assert_eq!(suite.active_unit("menu").as_deref(), Some("root"));
+
+        // Neither does it after reopening
+        simulation.run_to(&mut suite, 9.5, 0.05);
+        assert_eq!(suite.active_unit("menu").as_deref(), Some("menu"));
+        assert_eq!(confirmed.load(Ordering::SeqCst), 1);
+        simulation.run_to(&mut suite, 11.5, 0.05);
+        assert_eq!(confirmed.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,67 @@
+use super::*;
+
+#[derive(Debug)]
+struct IntervalState {
+    due_at: f64, // next point of the regular grid
+    fires_at: f64, // due_at plus jitter
+    random: u64, // xorshift state
+}
+
+impl IntervalState {
+    fn next_random(&mut self) -> f64 {
+        self.random ^= self.random << 13;
+        self.random ^= self.random >> 7;
+        self.random ^= self.random << 17;
+        (self.random >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// True once every `period` seconds, independent of when the task last ran.
+/// The first time is `offset` seconds after the first evaluation. With jitter
+/// every occurrence is delayed by a random amount up to `jitter` seconds,
+/// without shifting the following ones. Occurrences missed while the
+/// condition was not evaluated fire once, not repeatedly.
+#[derive(Debug)]
+pub struct Interval {
+    pub(crate) period: f64,
+    pub(crate) jitter: f64,
+    pub(crate) offset: f64,
+    state: Mutex<Option<IntervalState>>,
+}
+
+impl Interval {
+    pub fn every(period: f64) -> Box<Self> {
+        Box::new(Interval { period, jitter: 0., offset: 0., state: Mutex::new(None) })
+    }
+    pub fn with_jitter(mut self: Box<Self>, jitter: f64) -> Box<Self> {
+        self.jitter = jitter;
+        self
+    }
+    pub fn starting_after(mut self: Box<Self>, offset: f64) -> Box<Self> {
+        self.offset = offset;
+        self
+    }
+}
+
+impl Condition for Interval {
+    fn eval(&self, environment: &Environment, _: &RunningTreeState) -> Result<bool, TaskError> {
+        let mut state = self.state
+            .try_lock()
+            .map_err(|_| TaskError::IoError { comment: "Failed to lock interval state".to_string() })?;
+        let now = environment.now();
+        let state = state.get_or_insert_with(|| {
+            // Seeded per condition, so intervals with the same period spread out
+            let seed = (now.to_bits() ^ (self as *const Self as u64)) | 1;
+            let mut state = IntervalState { due_at: now + self.offset, fires_at: 0., random: seed };
+            state.fires_at = state.due_at + self.jitter * state.next_random();
+            state
+        });
+        if now < state.fires_at {
+            return Ok(false);
+        }
+        let period = self.period.max(f64::EPSILON);
+        state.due_at += (((now - state.due_at) / period).floor().max(0.) + 1.) * period;
+        state.fires_at = state.due_at + self.jitter * state.next_random();
+        Ok(true)
+    }
+}
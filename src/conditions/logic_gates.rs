@@ -34,7 +34,7 @@ impl Gates {
             Gates::And(existing_conditions) => existing_conditions.extend(conditions),
             Gates::Or(existing_conditions) => existing_conditions.extend(conditions),
             Gates::Not(inner) => {
-                if conditions.len() >= 1 {
+                if !conditions.is_empty() {
                     *inner = conditions.into_iter().next().unwrap();
                 }
             }
@@ -67,7 +67,7 @@ impl Condition for Gates {
             Gates::Not(condition) => Ok(!condition.eval(environment, running_tree)?),
         }
     }
-    fn as_automaticlt_initializable(&self) -> Option<Vec<AutomaticltInitializable>> {
+    fn as_automaticlt_initializable(&self) -> Option<Vec<AutomaticltInitializable<'_>>> {
         let mut initializables = Vec::new();
         match self {
             Gates::And(conditions) | Gates::Or(conditions) => {
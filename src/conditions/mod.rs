@@ -7,25 +7,36 @@ use crate::types::StateType;
 use crate::evaluator::enviorment::Environment;
 use crate::types::{PinHandler};
 use crate::evaluator::RunningTreeState;
-use crate::unix_now;
 
 pub mod app_state;
 pub mod digital_gpio;
 pub mod dispatch_tree;
 pub mod logic_gates;
 pub mod constants;
-
-pub use app_state::AppCondition;
+pub mod schedule;
+pub mod cron;
+pub mod interval;
+pub mod gesture;
+pub mod edge;
+pub mod pulse;
+
+pub use app_state::{AppCondition, AppComparison, HysteresisCondition, Operand};
 pub use digital_gpio::DigitalGpioCondition;
 pub use dispatch_tree::TreeCondition;
 pub use logic_gates::Gates;
 pub use constants::AllwaysTrue;
+pub use schedule::ScheduleCondition;
+pub use cron::{CronCondition, MissedRuns};
+pub use interval::Interval;
+pub use gesture::GestureCondition;
+pub use edge::{EdgeCondition, EdgeKind};
+pub use pulse::PulseCondition;
 
 
 
 pub trait Condition: Send + Sync + Debug {
     fn eval(&self, environment: &Environment, running_tree_state: &RunningTreeState) -> Result<bool, TaskError>;
-    fn as_automaticlt_initializable(&self) -> Option<Vec<AutomaticltInitializable>> {
+    fn as_automaticlt_initializable(&self) -> Option<Vec<AutomaticltInitializable<'_>>> {
         None
     }
 } 
@@ -34,4 +45,7 @@ pub trait Condition: Send + Sync + Debug {
 pub enum AutomaticltInitializable <'a>{
     AppCondition(&'a AppCondition),
     DigitalGpioCondition(&'a DigitalGpioCondition),
+    InputPin(u8),
+    OutputPin(u8),
+    PulseCounter(u8),
 }
@@ -0,0 +1,87 @@
+use super::*;
+use crate::types::{pulse_count_key, pulse_rate_key};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Threshold {
+    CountAtLeast(i64),
+    RateAbove(f64),
+    RateBelow(f64),
+}
+
+/// Compares the pulse count or rate of an input pin against a threshold.
+/// Enables pulse counting on the pin, see `InputPinConfig::count_pulses`.
+#[derive(Debug)]
+pub struct PulseCondition {
+    pub(crate) pin: u8,
+    threshold: Threshold,
+}
+
+impl PulseCondition {
+    pub fn count_at_least(pin: u8, count: i64) -> Box<Self> {
+        Box::new(PulseCondition { pin, threshold: Threshold::CountAtLeast(count) })
+    }
+    /// Rate in pulses per second.
+    pub fn rate_above(pin: u8, rate: f64) -> Box<Self> {
+        Box::new(PulseCondition { pin, threshold: Threshold::RateAbove(rate) })
+    }
+    pub fn rate_below(pin: u8, rate: f64) -> Box<Self> {
+        Box::new(PulseCondition { pin, threshold: Threshold::RateBelow(rate) })
+    }
+}
+
+impl Condition for PulseCondition {
+    fn eval(&self, environment: &Environment, _: &RunningTreeState) -> Result<bool, TaskError> {
+        let value = |key: String| environment.app_state.get(&key).cloned()
+            .ok_or(TaskError::IoError { comment: format!("Pin {} is not counting pulses", self.pin) });
+        Ok(match self.threshold {
+            Threshold::CountAtLeast(count) => value(pulse_count_key(self.pin))?.as_int() >= count,
+            Threshold::RateAbove(rate) => value(pulse_rate_key(self.pin))?.as_float() > rate,
+            Threshold::RateBelow(rate) => value(pulse_rate_key(self.pin))?.as_float() < rate,
+        })
+    }
+    fn as_automaticlt_initializable(&self) -> Option<Vec<AutomaticltInitializable<'_>>> {
+        Some(vec![AutomaticltInitializable::PulseCounter(self.pin)])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use super::*;
+    use crate::tasks::general_task::Task;
+    use crate::testing::Simulation;
+    use crate::types::StateType;
+
+    #[test]
+    fn count_restarts_after_a_reset() {
+        let simulation = Simulation::new();
+        // Active low, three pulses twice
+        simulation.gpio.load_script("pin 5 low at 1, high at 1.1, low at 1.2, high at 1.3, low at 1.4, high at 1.5, \
+            low at 3, high at 3.1, low at 3.2, high at 3.3, low at 3.4, high at 3.5").unwrap();
+        let batches = Arc::new(AtomicU32::new(0));
+        let counter = batches.clone();
+        let mut suite = simulation.suite(vec![("meter", vec![Task::new("batch")
+            .when_condition(PulseCondition::count_at_least(5, 3))
+            .with_action(move |environment| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                environment.write().unwrap().reset_pulses(5)
+            })
+            .to_eveluatable()])]);
+        let environment = suite.environment();
+        let count = || environment.read().unwrap().app_state.get(&pulse_count_key(5)).cloned();
+
+        simulation.run_to(&mut suite, 1.3, 0.1);
+        assert_eq!(count(), Some(StateType::Int(2)));
+        assert_eq!(batches.load(Ordering::SeqCst), 0);
+        simulation.run_to(&mut suite, 2., 0.1);
+        assert_eq!(count(), Some(StateType::Int(0)));
+        assert_eq!(batches.load(Ordering::SeqCst), 1);
+        simulation.run_to(&mut suite, 3.3, 0.1);
+        assert_eq!(count(), Some(StateType::Int(2)));
+        simulation.run_to(&mut suite, 4., 0.1);
+        assert_eq!(count(), Some(StateType::Int(0)));
+        assert_eq!(batches.load(Ordering::SeqCst), 2);
+    }
+}
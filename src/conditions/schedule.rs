@@ -0,0 +1,139 @@
+use super::*;
+use crate::clock::{LocalTime, Weekday};
+
+#[derive(Clone, Debug)]
+struct Window {
+    start: u32, // seconds of the day
+    end: u32,
+    days: Vec<Weekday>, // days the window starts on
+}
+
+impl Window {
+    fn contains(&self, now: &LocalTime) -> bool {
+        let time = now.seconds_of_day();
+        if self.start == self.end {
+            return self.days.contains(&now.weekday);
+        }
+        if self.start < self.end {
+            return self.days.contains(&now.weekday) && self.start <= time && time < self.end;
+        }
+        // Crosses midnight, the part after it belongs to the day before
+        (self.days.contains(&now.weekday) && time >= self.start)
+            || (self.days.contains(&now.weekday.previous()) && time < self.end)
+    }
+}
+
+/// True within daily time windows in the local timezone, e.g.
+/// `ScheduleCondition::new().window_on(&Weekday::WEEKDAYS, "07:00", "22:00")`.
+/// A window ending before it starts crosses midnight, one with equal start
+/// and end lasts the whole day. Times are read from the suite clock.
+#[derive(Debug)]
+pub struct ScheduleCondition {
+    windows: Vec<Window>,
+    invalid: Option<String>, // reported when evaluated, the builders can not fail
+}
+
+fn parse_time_of_day(time: &str) -> Option<u32> {
+    let mut parts = time.trim().split(':');
+    let hour: u32 = parts.next()?.parse().ok()?;
+    let minute: u32 = parts.next()?.parse().ok()?;
+    let second: u32 = match parts.next() {
+        Some(second) => second.parse().ok()?,
+        None => 0,
+    };
+    if parts.next().is_some() || hour > 24 || minute > 59 || second > 59 || (hour == 24 && minute + second > 0) {
+        return None;
+    }
+    Some((hour * 3600 + minute * 60 + second) % 86400)
+}
+
+impl ScheduleCondition {
+    pub fn new() -> Box<Self> {
+        Box::new(ScheduleCondition { windows: Vec::new(), invalid: None })
+    }
+    /// Window on every day, times as `HH:MM` or `HH:MM:SS`.
+    pub fn window(self: Box<Self>, start: &str, end: &str) -> Box<Self> {
+        self.window_on(&Weekday::ALL, start, end)
+    }
+    /// Window starting on the given days.
+    pub fn window_on(mut self: Box<Self>, days: &[Weekday], start: &str, end: &str) -> Box<Self> {
+        match (parse_time_of_day(start), parse_time_of_day(end)) {
+            (Some(start), Some(end)) => self.windows.push(Window { start, end, days: days.to_vec() }),
+            _ => self.invalid = Some(format!("Invalid schedule window {} - {}", start, end)),
+        }
+        self
+    }
+}
+
+impl Condition for ScheduleCondition {
+    fn eval(&self, environment: &Environment, _: &RunningTreeState) -> Result<bool, TaskError> {
+        if let Some(invalid) = &self.invalid {
+            return Err(TaskError::TriggerError { comment: invalid.clone() });
+        }
+        let now = LocalTime::from_unix(environment.now());
+        Ok(self.windows.iter().any(|window| window.contains(&now)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::with_timezone;
+
+    fn at(weekday: Weekday, hour: u32, minute: u32) -> LocalTime {
+        LocalTime { year: 2024, month: 6, day: 10, weekday, hour, minute, second: 0 }
+    }
+
+    #[test]
+    fn parses_times_of_day() {
+        assert_eq!(parse_time_of_day("07:30"), Some(7 * 3600 + 30 * 60));
+        assert_eq!(parse_time_of_day(" 7:05:09 "), Some(7 * 3600 + 5 * 60 + 9));
+        assert_eq!(parse_time_of_day("00:00"), Some(0));
+        assert_eq!(parse_time_of_day("24:00"), Some(0));
+        for invalid in ["24:01", "12:60", "12:00:60", "1:2:3:4", "12", "ab:cd", "", "-1:00"] {
+            assert_eq!(parse_time_of_day(invalid), None, "{} should be invalid", invalid);
+        }
+    }
+
+    #[test]
+    fn window_within_a_day() {
+        let window = Window { start: 7 * 3600, end: 22 * 3600, days: Weekday::WEEKDAYS.to_vec() };
+        assert!(window.contains(&at(Weekday::Mon, 7, 0)));
+        assert!(window.contains(&at(Weekday::Fri, 21, 59)));
+        assert!(!window.contains(&at(Weekday::Mon, 22, 0)));
+        assert!(!window.contains(&at(Weekday::Mon, 6, 59)));
+        assert!(!window.contains(&at(Weekday::Sat, 12, 0)));
+    }
+
+    #[test]
+    fn window_across_midnight_belongs_to_its_start_day() {
+        let window = Window { start: 22 * 3600, end: 6 * 3600, days: vec![Weekday::Fri] };
+        assert!(window.contains(&at(Weekday::Fri, 23, 0)));
+        assert!(window.contains(&at(Weekday::Sat, 5, 59)));
+        assert!(!window.contains(&at(Weekday::Sat, 6, 0)));
+        assert!(!window.contains(&at(Weekday::Fri, 5, 0)));
+        assert!(!window.contains(&at(Weekday::Sat, 23, 0)));
+    }
+
+    #[test]
+    fn window_with_equal_ends_lasts_the_day() {
+        let window = Window { start: 0, end: 0, days: Weekday::WEEKEND.to_vec() };
+        assert!(window.contains(&at(Weekday::Sun, 0, 0)));
+        assert!(window.contains(&at(Weekday::Sat, 23, 59)));
+        assert!(!window.contains(&at(Weekday::Mon, 0, 0)));
+    }
+
+    #[test]
+    fn windows_use_the_local_timezone() {
+        let window = Window { start: 22 * 3600, end: 6 * 3600, days: vec![Weekday::Fri] };
+        let friday_2130_utc = 1718400600.;
+        with_timezone("UTC0", || assert!(!window.contains(&LocalTime::from_unix(friday_2130_utc))));
+        with_timezone("CET-1CEST,M3.5.0,M10.5.0/3", || assert!(window.contains(&LocalTime::from_unix(friday_2130_utc))));
+    }
+
+    #[test]
+    fn invalid_windows_are_kept_for_evaluation() {
+        assert!(ScheduleCondition::new().window("07:00", "25:00").invalid.is_some());
+        assert!(ScheduleCondition::new().window("22:00", "06:00").invalid.is_none());
+    }
+}
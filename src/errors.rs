@@ -5,4 +5,26 @@ custom_error!{pub TaskError
     ActionError{comment:String} = "{comment}",
     IoError{comment:String} = "{comment}",
     SystemError{comment:String} = "{comment}",
-}
\ No newline at end of file
+    StateError{comment:String} = "{comment}",
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TaskErrorKind {
+    TriggerError,
+    ActionError,
+    IoError,
+    SystemError,
+    StateError,
+}
+
+impl TaskError {
+    pub fn kind(&self) -> TaskErrorKind {
+        match self {
+            TaskError::TriggerError { .. } => TaskErrorKind::TriggerError,
+            TaskError::ActionError { .. } => TaskErrorKind::ActionError,
+            TaskError::IoError { .. } => TaskErrorKind::IoError,
+            TaskError::SystemError { .. } => TaskErrorKind::SystemError,
+            TaskError::StateError { .. } => TaskErrorKind::StateError,
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use tokio::runtime::Handle;
+use tokio::time::MissedTickBehavior;
+
+use crate::errors::TaskError;
+use crate::evaluator::enviorment::Environment;
+use crate::evaluator::suite::Suite;
+use crate::tasks::cancellation::CancellationToken;
+use crate::tasks::general_task::Task;
+
+use super::dispatcher::finish_task;
+use super::logger::LogLevel;
+use super::dispatcher::{EVENT_POLL_STEP, SHUTDOWN_POLL_STEP};
+use super::shutdown::install_signal_handlers;
+use super::worker_pool::{JobState, JobStatus};
+
+
+/// Runs `task` on `runtime`, async actions as tokio tasks and blocking
+/// ones through `spawn_blocking`.
+pub(crate) fn spawn(runtime: &Handle, task: Arc<Task>, environment: Arc<RwLock<Environment>>, token: CancellationToken) -> Arc<JobStatus> {
+    let handle = if task.is_async() {
+        runtime.spawn(async move {
+            let result = task.run_async(environment.clone(), token).await;
+            finish_task(&task, &environment, result);
+        })
+    } else {
+        runtime.spawn_blocking(move || {
+            let result = task.run(environment.clone(), token);
+            finish_task(&task, &environment, result);
+        })
+    };
+    let status = JobStatus::new(JobState::Running);
+    let tracked = status.clone();
+    runtime.spawn(async move {
+        tracked.set(match handle.await {
+            Ok(_) => JobState::Finished,
+            Err(_) => JobState::Panicked,
+        });
+    });
+    status
+}
+
+/// Async counterpart of `suite_dispatcher`, has to be awaited inside a tokio
+/// runtime with the time driver enabled. The suite is evaluated on a tokio
+/// interval of `sleep_time` (in real time, not on the suite clock), or on
+/// events in event driven mode.
+pub async fn async_suite_dispatcher(mut suite: Suite<'_>) -> Result<(), TaskError> {
+    let handle_signals = suite.suite_options.handle_signals;
+    let previous_handlers = handle_signals.then(install_signal_handlers);
+    suite.runtime = Some(Handle::current());
+    suite.structure.read().unwrap().log("Entering async loop", LogLevel::Debug);
+
+    let mut interval = tokio::time::interval(Duration::from_nanos(suite.suite_options.sleep_time.unwrap_or(250_000_000)));
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let events = suite.structure.read().unwrap().events.clone();
+    let result = loop {
+        if let Err(error) = suite.tick() {
+            break Err(error);
+        }
+        if suite.stop_requested() {
+            break Ok(());
+        }
+        if !suite.suite_options.event_driven {
+            interval.tick().await;
+            continue;
+        }
+        let deadline = Instant::now() + suite.next_tick_in(&suite.structure.read().unwrap());
+        while !suite.stop_requested() {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() || events.wait_async(remaining.min(EVENT_POLL_STEP)).await {
+                break;
+            }
+        }
+    };
+
+    suite.begin_shutdown();
+    let started = tokio::time::Instant::now();
+    while suite.wait_for_tasks(started.elapsed()) {
+        tokio::time::sleep(SHUTDOWN_POLL_STEP).await;
+    }
+    let shutdown = suite.finish_shutdown();
+    drop(previous_handlers);
+    shutdown?;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::conditions::constants::AllwaysTrue;
+    use crate::evaluator::suite::SutieOptions;
+    use crate::gpio::SimulatedGpio;
+
+    #[test]
+    fn shutdown_grace_period_passes_with_a_manual_clock() {
+        let clock = ManualClock::starting_at(0.);
+        let mut options = SutieOptions::new();
+        options.gpio_backend = Some(Arc::new(SimulatedGpio::with_clock(Arc::new(clock.clone()))));
+        options.clock = Some(Arc::new(clock));
+        options.handle_signals = false;
+        options.shutdown_grace_period = 0.2;
+        let stuck = Task::new("stuck")
+            .when_condition(AllwaysTrue::new().on_flank())
+            .with_cancellable_action(|_, token| {
+                while !token.is_cancelled() {
+                    std::thread::sleep(Duration::from_millis(5));
+                }
+                Ok(())
+            });
+        let suite = Suite::new(HashMap::from([("stuck", vec![stuck.to_eveluatable()])]), None, Some(options)).unwrap();
+        suite.shutdown_handle().shutdown();
+
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_time().build().unwrap();
+        let started = Instant::now();
+        let result = runtime.block_on(async {
+            tokio::time::timeout(Duration::from_secs(5), async_suite_dispatcher(suite)).await
+        });
+        assert!(matches!(result, Ok(Ok(()))));
+        assert!(started.elapsed() >= Duration::from_millis(200));
+    }
+}
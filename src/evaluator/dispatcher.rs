@@ -8,24 +8,119 @@ use std::sync::RwLock;
 
 
 use crate::tasks::{Conditional, ConditionalTypes};
-use crate::unix_now;
+use crate::tasks::general_task::Task;
 use crate::evaluator::suite::Suite;
 use crate::types::StateType;
 use crate::errors::TaskError;
 
+use crate::tasks::cancellation::CancellationToken;
+
 use super::logger::LogLevel;
+use super::worker_pool::{WorkerPool, JobStatus, JobState};
+use super::shutdown::{install_signal_handlers, signal_received};
 use super::{RunningTreeState, enviorment, EvalResult};
 
 
+pub(crate) const EVENT_POLL_STEP: time::Duration = time::Duration::from_millis(100);
+pub(crate) const SHUTDOWN_POLL_STEP: time::Duration = time::Duration::from_millis(10);
+
+#[derive(Debug)]
+enum Execution {
+    Thread(JoinHandle<()>),
+    Pooled(Arc<JobStatus>), // holds a worker until the action returns, even after a timeout
+    #[cfg(feature = "async")]
+    Spawned(Arc<JobStatus>), // tokio tasks
+}
+
+impl Execution {
+    fn is_finished(&self) -> bool {
+        match self {
+            Execution::Thread(handle) => handle.is_finished(),
+            Execution::Pooled(status) => status.is_finished(),
+            #[cfg(feature = "async")]
+            Execution::Spawned(status) => status.is_finished(),
+        }
+    }
+    /// False while the job waits in the worker queue.
+    fn has_started(&self) -> bool {
+        match self {
+            Execution::Pooled(status) => status.get() != JobState::Queued,
+            _ => true,
+        }
+    }
+    fn join(self) -> JobState {
+        match self {
+            Execution::Thread(handle) => match handle.join() {
+                Ok(_) => JobState::Finished,
+                Err(_) => JobState::Panicked,
+            },
+            Execution::Pooled(status) => status.get(),
+            #[cfg(feature = "async")]
+            Execution::Spawned(status) => status.get(),
+        }
+    }
+}
+
+/// Where triggered tasks are executed.
+#[derive(Clone, Copy)]
+struct Executor<'a> {
+    worker_pool: Option<&'a WorkerPool>,
+    #[cfg(feature = "async")]
+    runtime: Option<&'a tokio::runtime::Handle>,
+}
+
+impl Executor<'_> {
+    /// Starts `task`, returns None if it was skipped because the worker queue is full.
+    fn execute(&self, task: Arc<Task>, environment: Arc<RwLock<enviorment::Environment>>, token: CancellationToken) -> Option<Execution> {
+        #[cfg(feature = "async")]
+        if let Some(runtime) = self.runtime {
+            // Blocking actions prefer the worker pool over spawn_blocking
+            if task.is_async() || self.worker_pool.is_none() {
+                return Some(Execution::Spawned(super::async_dispatcher::spawn(runtime, task, environment, token)));
+            }
+        }
+        let job = move || {
+            let result = task.run(environment.clone(), token);
+            finish_task(&task, &environment, result);
+        };
+        match self.worker_pool {
+            Some(pool) => pool.submit(Box::new(job)).map(Execution::Pooled),
+            None => Some(Execution::Thread(thread::spawn(job))),
+        }
+    }
+}
+
+/// Logs a failed run and wakes the event driven dispatcher, the task may be triggered again.
+pub(crate) fn finish_task(task: &Task, environment: &Arc<RwLock<enviorment::Environment>>, result: Result<(), TaskError>) {
+    let env = environment.read().unwrap();
+    if let Err(error) = result {
+        env.log(&format!("Task {} failed: {}", task.get_name(), error), LogLevel::Error);
+    }
+    env.events.notify();
+}
+
+#[derive(Debug)]
+pub(crate) struct RunningTask {
+    execution: Execution,
+    started_at: Option<f64>, // None while queued, the timeout only counts run time
+    timeout: Option<f64>,
+    token: CancellationToken,
+    cancelled: bool,
+}
+
 fn evaluate_context_v2<'a>(
-    running_tasks: &mut HashMap<String, JoinHandle<()>>,
+    running_tasks: &mut HashMap<String, RunningTask>,
+    executor: Executor,
     unit: &'a ConditionalTypes,
     environment: Arc<RwLock<enviorment::Environment>>,
     running_tree: &RunningTreeState) -> EvalResult<'a> {
     let as_conditional = unit.get_inner_conditional();
-    if (as_conditional.min_delay_between_exec() > 
-        unix_now!(f64) - environment.read().unwrap().app_state.get(&format!("{}_executed", as_conditional.get_name()))
-                            .or_else(|| Some(&StateType::Int(0.))).unwrap().as_int()) ||
+    let since_last_exec = {
+        let env = environment.read().unwrap();
+        env.now() - env.app_state.get(&format!("{}_executed", as_conditional.get_name()))
+            .unwrap_or(&StateType::Float(0.)).as_float()
+    };
+    if as_conditional.min_delay_between_exec() > since_last_exec ||
             running_tasks.contains_key(&as_conditional.get_name()) {
             return EvalResult::Stay;
         }
@@ -42,19 +137,30 @@ fn evaluate_context_v2<'a>(
                     if !result {
                         return EvalResult::Stay;
                     }
-                    environment.read().unwrap().log(&format!("Executing Task: {}", task.get_name()), LogLevel::Debug);
-                    environment.write()
-                        .unwrap()
-                        .app_state
-                        .insert(format!("{}_executed", task.get_name()), StateType::Int(unix_now!() as f64));
-                    
-                    let enviorment = environment.clone();
-                    let task = Arc::clone(task); // Clone the Arc to safely share between threads
-                    running_tasks.insert(task.get_name(), thread::spawn(move || {
-                        if let Err(error) = task.action(enviorment.clone()) {
-                            enviorment.read().unwrap().log(&format!("Task {} failed: {}", task.get_name(), error), LogLevel::Error);
-                        };
-                    }));
+                    let token = CancellationToken::new();
+                    let name = task.get_name();
+                    let timeout = task.timeout();
+                    // Clone the Arc to safely share between threads
+                    let execution = match executor.execute(Arc::clone(task), environment.clone(), token.clone()) {
+                        Some(execution) => execution,
+                        None => {
+                            environment.read().unwrap().log(&format!("Worker queue is full, skipping Task: {}", name), LogLevel::Warning);
+                            return EvalResult::Stay;
+                        }
+                    };
+
+                    environment.read().unwrap().log(&format!("Executing Task: {}", name), LogLevel::Debug);
+                    let mut env = environment.write().unwrap();
+                    let now = env.now();
+                    env.app_state.set_quiet(&format!("{}_executed", name), StateType::Float(now));
+                    drop(env);
+                    running_tasks.insert(name, RunningTask {
+                        started_at: execution.has_started().then_some(now),
+                        execution,
+                        timeout,
+                        token,
+                        cancelled: false,
+                    });
                 }
                 ConditionalTypes::TaskContext(context) => {
                     if !running_tree.currently_active{
@@ -65,14 +171,14 @@ fn evaluate_context_v2<'a>(
                         };
                     };
                     for subtask in context.subunits.iter().collect::<Vec<_>>() {
-                        let result = evaluate_context_v2(running_tasks, subtask, environment.clone(), &running_tree.get_running_tree_for_subtask());
+                        let result = evaluate_context_v2(running_tasks, executor, subtask, environment.clone(), &running_tree.get_running_tree_for_subtask());
                         if let EvalResult::MoveTo(result) = result {
                             return EvalResult::MoveTo(result);
                         }
                     }
                     if !result {
                         if let Some(on_exit) = context.on_exit.clone() {
-                            evaluate_context_v2(running_tasks, &ConditionalTypes::Task(on_exit), environment, running_tree);
+                            evaluate_context_v2(running_tasks, executor, &ConditionalTypes::Task(on_exit), environment, running_tree);
                         }
                         return EvalResult::MoveOut;
                     }
@@ -86,41 +192,45 @@ fn evaluate_context_v2<'a>(
 
     }
 
-pub fn suite_dispatcher(suite: Suite) -> Result<(), TaskError> {
-    let environment = suite.structure;
-    let mut running_tasks: HashMap<String, JoinHandle<()>> = HashMap::new();
+impl<'a> Suite<'a> {
+    /// Runs one full evaluation pass: refreshes the input GPIOs, evaluates
+    /// every tree once and joins the tasks which have finished since.
+    pub fn tick(&mut self) -> Result<(), TaskError> {
+        let environment = self.structure.clone();
 
-    environment.read().unwrap().log("Entering loop", LogLevel::Debug);
-    
-    let mut context_pointer_tree: HashMap<&str, (Vec<&ConditionalTypes>, RunningTreeState)> = HashMap::new();
-    for (name, unit) in suite.tasks.iter() {
-        context_pointer_tree.insert(*name, (vec![unit], RunningTreeState::new()));
-    }
-    loop {
         // Update gpio states from environment
-        environment.write()
-            .unwrap()
-            .input_gpios
-            .iter_mut()
-            .for_each(|(_, value)|{
-                value.last_state = value.current_state;
-                    value.current_state = value.handler.is_low();
-                    if value.current_state != value.last_state {
-                        value.last_change = unix_now!(f64);
-                }});
+        let mut env = environment.write().unwrap();
+        let now = env.now();
+        env.tick += 1;
+        let tick = env.tick;
+        env.input_gpios
+            .values_mut()
+            .for_each(|handler| handler.refresh(now, tick));
+        env.output_gpios
+            .values_mut()
+            .for_each(|handler| handler.sample(tick));
+        env.record_pulses();
+        drop(env);
         
         
+        let executor = Executor {
+            worker_pool: self.worker_pool.as_ref(),
+            #[cfg(feature = "async")]
+            runtime: self.runtime.as_ref(),
+        };
+
         // Execute Units
-        for (name, unit) in suite.tasks.iter(){
+        for (name, unit) in self.tasks.iter(){
             let mut repeat = true;
-            if let Some((active_iteration, tree_state)) = context_pointer_tree.get_mut(name){
+            if let Some((active_iteration, tree_state)) = self.active_units.get_mut(name){
                 while repeat {
-                    let current_context = active_iteration.last().copied().unwrap_or(unit);
+                    let current_context = active_iteration.last().cloned().unwrap_or_else(|| unit.clone());
                     let result = evaluate_context_v2(
-                        &mut running_tasks,
-                        current_context,
+                        &mut self.running_tasks,
+                        executor,
+                        &current_context,
                         environment.clone(),
-                        &tree_state);
+                        tree_state);
                     
                     match result {
                         EvalResult::Stay => {repeat = false; tree_state.first_iteration_after_move = false;},
@@ -131,7 +241,7 @@ pub fn suite_dispatcher(suite: Suite) -> Result<(), TaskError> {
                             environment.read().unwrap().log(&format!("Moving out of context: {}", current_context.get_inner_conditional().get_name()), LogLevel::Debug);
                         },
                         EvalResult::MoveTo(result) => {
-                            active_iteration.push(result);
+                            active_iteration.push(result.clone());
                             tree_state.first_iteration_after_move = true;
                             tree_state.moved_in_from_back = false;
                             environment.read().unwrap().log(&format!("Moving to context: {}", result.get_inner_conditional().get_name()), LogLevel::Debug);
@@ -144,18 +254,252 @@ pub fn suite_dispatcher(suite: Suite) -> Result<(), TaskError> {
         }
         
         
+        self.join_finished_tasks();
+        self.persist(false);
+        Ok(())
+    }
+
+    /// Saves the persisted app_state keys if they are due, or right away if `now`.
+    fn persist(&mut self, now: bool) {
+        if let Some(persistence) = self.persistence.as_mut() {
+            let env = self.structure.read().unwrap_or_else(|e| e.into_inner());
+            let result = if now { persistence.save(&env) } else { persistence.save_if_due(&env) };
+            if let Err(error) = result {
+                env.log(&error.to_string(), LogLevel::Error);
+            }
+        }
+    }
+
+    pub(crate) fn join_finished_tasks(&mut self) {
         // Other methodes would require the implementation of Copy trait
-        let mut unfinished_tasks: HashMap<String, JoinHandle<()>> = HashMap::new();
-        for (name, task) in running_tasks.into_iter() {
-            if task.is_finished() {
-                if let Err(_) = task.join() {
-                    environment.read().unwrap().log(&format!("Task {} could not re-join main loop", name), LogLevel::Error);
+        let mut unfinished_tasks: HashMap<String, RunningTask> = HashMap::new();
+        let now = self.structure.read().unwrap_or_else(|e| e.into_inner()).now();
+        for (name, mut task) in self.running_tasks.drain() {
+            if task.execution.is_finished() {
+                // A cancelled job finishing late keeps its `_timed_out` flag
+                if task.timeout.is_some() && !task.cancelled {
+                    // Declared as Bool when the environment was initialized
+                    let _ = self.structure.write().unwrap_or_else(|e| e.into_inner())
+                        .app_state.set(&format!("{}_timed_out", name), StateType::Bool(false));
+                }
+                match task.execution.join() {
+                    JobState::Panicked => {
+                        let mut env = self.structure.write().unwrap_or_else(|e| e.into_inner());
+                        env.log(&format!("Task {} could not re-join main loop, driving outputs to their safe state", name), LogLevel::Error);
+                        env.drive_safe_state();
+                    },
+                    JobState::Dropped => {
+                        self.structure.read().unwrap_or_else(|e| e.into_inner())
+                            .log(&format!("Task {} was dropped from the full worker queue", name), LogLevel::Warning);
+                    },
+                    _ => {},
+                }
+                continue;
+            }
+            if task.started_at.is_none() && task.execution.has_started() {
+                task.started_at = Some(now);
+            }
+            let timed_out = task.timeout.zip(task.started_at)
+                .is_some_and(|(timeout, started_at)| now - started_at > timeout);
+            if timed_out && !task.cancelled {
+                // Running actions can not be killed, they are only cancelled
+                task.token.cancel();
+                task.cancelled = true;
+                let mut env = self.structure.write().unwrap_or_else(|e| e.into_inner());
+                env.log(&format!("Task {} exceeded its timeout of {}s and was cancelled", name, task.timeout.unwrap_or(0.)), LogLevel::Error);
+                let _ = env.app_state.set(&format!("{}_timed_out", name), StateType::Bool(true));
+                // Threads and tokio tasks are left detached and the task can be triggered
+                // again. A pool job keeps blocking the name until it frees its worker,
+                // otherwise copies of it would queue up behind it.
+                if !matches!(task.execution, Execution::Pooled(_)) {
+                    continue;
+                }
+            }
+            unfinished_tasks.insert(name.to_string(), task);
+        }
+        self.running_tasks = unfinished_tasks;
+    }
+
+    /// Ticks the suite until `predicate` returns true after a tick or a
+    /// shutdown was requested, sleeping `sleep_time` on the suite clock in between.
+    /// In event driven mode it waits for the next event or `fallback_tick` instead.
+    pub fn run_until<F: FnMut(&Suite<'a>) -> bool>(&mut self, mut predicate: F) -> Result<(), TaskError> {
+        loop {
+            self.tick()?;
+            if predicate(self) || self.shutdown.is_shutdown_requested() {
+                return Ok(());
+            }
+            if self.suite_options.event_driven {
+                self.wait_for_event();
+                continue;
+            }
+            let clock = self.structure.read().unwrap().clock();
+            clock.sleep(time::Duration::from_nanos(self.suite_options.sleep_time.unwrap_or(250_000_000)));
+        }
+    }
+
+    /// Waits for an event, at most `fallback_tick` seconds in real time.
+    fn wait_for_event(&self) {
+        let env = self.structure.read().unwrap();
+        let events = env.events.clone();
+        let deadline = time::Instant::now() + self.next_tick_in(&env);
+        drop(env);
+        // Short steps, so shutdown requests and signals are still picked up
+        while !self.stop_requested() {
+            let remaining = deadline.saturating_duration_since(time::Instant::now());
+            if remaining.is_zero() || events.wait(remaining.min(EVENT_POLL_STEP)) {
+                return;
+            }
+        }
+    }
+
+    /// The fallback tick, or earlier if a debounced input is about to settle.
+    pub(crate) fn next_tick_in(&self, environment: &enviorment::Environment) -> time::Duration {
+        let seconds = environment.next_input_settle()
+            .map_or(self.suite_options.fallback_tick, |settle| settle.min(self.suite_options.fallback_tick));
+        time::Duration::from_secs_f64(seconds.max(0.))
+    }
+
+    pub(crate) fn stop_requested(&self) -> bool {
+        self.shutdown.is_shutdown_requested() || (self.suite_options.handle_signals && signal_received())
+    }
+
+    /// Waits up to `shutdown_grace_period` seconds for running tasks to finish
+    /// and then runs the `on_exit` tasks of all active units, innermost first.
+    pub fn shutdown(&mut self) -> Result<(), TaskError> {
+        self.begin_shutdown();
+        let started = time::Instant::now();
+        while self.wait_for_tasks(started.elapsed()) {
+            thread::sleep(SHUTDOWN_POLL_STEP);
+        }
+        self.finish_shutdown()
+    }
+
+    /// Joins finished tasks, true while others are still running and `elapsed`
+    /// is within the grace period. Measured in real time, nothing advances a
+    /// `ManualClock` during shutdown.
+    pub(crate) fn wait_for_tasks(&mut self, elapsed: time::Duration) -> bool {
+        self.join_finished_tasks();
+        !self.running_tasks.is_empty() && elapsed.as_secs_f64() < self.suite_options.shutdown_grace_period
+    }
+
+    pub(crate) fn begin_shutdown(&mut self) {
+        self.shutdown.shutdown();
+        self.structure.read().unwrap().log("Shutting down", LogLevel::Info);
+        self.join_finished_tasks();
+    }
+
+    pub(crate) fn finish_shutdown(&mut self) -> Result<(), TaskError> {
+        let environment = self.structure.clone();
+        for (name, task) in self.running_tasks.iter() {
+            task.token.cancel();
+            environment.read().unwrap().log(&format!("Task {} did not finish within the grace period", name), LogLevel::Warning);
+        }
+
+        for (active_iteration, _) in self.active_units.values_mut() {
+            for unit in active_iteration.iter().skip(1).rev() {
+                if let ConditionalTypes::TaskContext(context) = unit {
+                    if let Some(on_exit) = &context.on_exit {
+                        environment.read().unwrap().log(&format!("Executing on_exit of {}", context.get_name()), LogLevel::Debug);
+                        if let Err(error) = on_exit.action(environment.clone()) {
+                            environment.read().unwrap().log(&format!("Task {} failed: {}", on_exit.get_name(), error), LogLevel::Error);
+                        }
+                    }
                 }
-            } else {
-                unfinished_tasks.insert(name.to_string(), task);
             }
+            active_iteration.truncate(1);
         }
-        running_tasks = unfinished_tasks;
-        thread::sleep(time::Duration::new(0, suite.suite_options.sleep_time.or_else(|| Some(250_000_000)).unwrap() as u32));
+        environment.write().unwrap().drive_safe_state();
+        self.persist(true);
+        Ok(())
+    }
+}
+
+pub fn suite_dispatcher(mut suite: Suite) -> Result<(), TaskError> {
+    let handle_signals = suite.suite_options.handle_signals;
+    let previous_handlers = handle_signals.then(install_signal_handlers);
+    suite.structure.read().unwrap().log("Entering loop", LogLevel::Debug);
+    let result = suite.run_until(|_| handle_signals && signal_received());
+    let shutdown = suite.shutdown();
+    drop(previous_handlers); // Ctrl-C stops the host program again
+    shutdown?;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use super::*;
+    use crate::clock::Clock;
+    use crate::conditions::constants::AllwaysTrue;
+    use crate::conditions::digital_gpio::DigitalGpioCondition;
+    use crate::tasks::task_context::Unit;
+    use crate::testing::{counting_task, Simulation};
+
+    #[test]
+    fn simulated_button_drives_delays_and_units() {
+        let simulation = Simulation::new();
+        // Inputs are active low, so low means pressed
+        simulation.gpio.load_script("pin 17 low at t=1, high at t=5").unwrap();
+
+        let held = Arc::new(AtomicU32::new(0));
+        let inside = Arc::new(AtomicU32::new(0));
+        let left = Arc::new(AtomicU32::new(0));
+        let mut suite = simulation.suite(vec![
+            ("held", vec![counting_task("held", &held)
+                .when_condition(DigitalGpioCondition::new_input(17).after_delay(2.).on_flank())
+                .to_eveluatable()]),
+            ("menu", vec![Unit::new("pressed")
+                .when_condition(DigitalGpioCondition::new_input(17))
+                .stay_while_condition(DigitalGpioCondition::new_input(17))
+                .subunit(counting_task("inside", &inside)
+                    .when_condition(DigitalGpioCondition::new_input(17).on_flank())
+                    .to_eveluatable())
+                .on_exit(counting_task("left", &left).when_condition(AllwaysTrue::new()))
+                .to_eveluatable()]),
+        ]);
+
+        simulation.tick_at(&mut suite, 0.5);
+        assert_eq!(suite.active_unit("menu").as_deref(), Some("root"));
+
+        simulation.tick_at(&mut suite, 1.5);
+        simulation.tick_at(&mut suite, 2.);
+        assert_eq!(suite.active_unit("menu").as_deref(), Some("pressed"));
+        assert_eq!(inside.load(Ordering::SeqCst), 1);
+        assert_eq!(held.load(Ordering::SeqCst), 0);
+
+        simulation.tick_at(&mut suite, 2.9);
+        assert_eq!(held.load(Ordering::SeqCst), 0);
+        simulation.tick_at(&mut suite, 3.1);
+        simulation.tick_at(&mut suite, 4.);
+        assert_eq!(held.load(Ordering::SeqCst), 1);
+        assert_eq!(inside.load(Ordering::SeqCst), 1);
+
+        simulation.tick_at(&mut suite, 5.5);
+        simulation.tick_at(&mut suite, 6.);
+        assert_eq!(suite.active_unit("menu").as_deref(), Some("root"));
+        assert_eq!(left.load(Ordering::SeqCst), 1);
+        assert_eq!(held.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn shutdown_waits_for_running_tasks_in_real_time() {
+        let simulation = Simulation::new();
+        let finished = Arc::new(AtomicU32::new(0));
+        let counter = finished.clone();
+        let slow = Task::new("slow")
+            .when_condition(AllwaysTrue::new().on_flank())
+            .with_action(move |_| {
+                thread::sleep(time::Duration::from_millis(300));
+                counter.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            });
+        let mut suite = simulation.suite(vec![("slow", vec![slow.to_eveluatable()])]);
+        suite.tick().unwrap();
+        assert!(suite.is_task_running("slow"));
+        suite.shutdown().unwrap();
+        assert_eq!(finished.load(Ordering::SeqCst), 1);
+        assert_eq!(simulation.clock.now(), 0.);
     }
-}
\ No newline at end of file
+}
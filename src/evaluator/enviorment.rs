@@ -1,31 +1,45 @@
 extern crate custom_error;
 use core::fmt;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::collections::hash_map::Entry;
 use std::path::PathBuf;
-use rppal::gpio::Gpio;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::Ordering;
 
 use std::thread::{self};
 use std::sync::mpsc::{self, Sender};
 
-use crate::unix_now;
 use crate::errors::TaskError;
 use crate::types::StateType;
-use crate::types::InputPinHandler;
+use crate::types::{InputPinHandler, PulseCounter, pulse_count_key, pulse_rate_key};
 use crate::lcd_driver::LCDdriver;
-use crate::tasks::ConditionalTypes;
+use crate::gpio::{GpioBackend, default_backend};
+use crate::gpio::safe_state::SafeOutputs;
+use crate::clock::{Clock, default_clock};
+use crate::tasks::{Conditional, ConditionalTypes};
 
-use crate::types::OutputPinHandler;
+use crate::types::{OutputPinHandler, OutputPinConfig, InputPinConfig};
 use super::logger::{logger, LoggerCommand, LogLevel};
+use super::suite::SutieOptions;
+use super::events::EventNotifier;
+use super::state_store::StateStore;
 
 
 #[derive(Debug)]
 pub struct Environment {
     pub input_gpios: HashMap<u8, InputPinHandler>,
-    pub app_state: HashMap<String, StateType>,
+    pub app_state: StateStore,
     pub output_gpios: HashMap<u8, OutputPinHandler>,
     pub lcd_driver: Result<LCDdriver, PathBuf>,
     logger: Sender<LoggerCommand>,
     pub (crate) pid: u32,
+    gpio_backend: Arc<dyn GpioBackend>,
+    clock: Arc<dyn Clock>,
+    output_pin_config: HashMap<u8, OutputPinConfig>,
+    input_pin_config: HashMap<u8, InputPinConfig>,
+    pub(crate) safe_outputs: SafeOutputs,
+    pub(crate) events: EventNotifier,
+    pub(crate) tick: u64, // counts dispatcher ticks, starting at 1
 }
 
 fn recursively_initialize(mut enviorment: Environment, unit: &ConditionalTypes) -> Result<Environment, TaskError> {
@@ -37,38 +51,39 @@ fn recursively_initialize(mut enviorment: Environment, unit: &ConditionalTypes)
             for auto_initializable in automaticlt_initializables{
                 match auto_initializable {
                     crate::conditions::AutomaticltInitializable::AppCondition(app_condition) => {
-                        if !enviorment.app_state.contains_key(app_condition.key) {
-                            enviorment.app_state.insert(
-                                app_condition.key.to_string(),
-                                app_condition.value.as_default()
-                            );
-                        }
+                        // Fails early if two conditions compare the key as different types
+                        enviorment.app_state.declare(app_condition.key, app_condition.value.as_default())?;
                     },
                     crate::conditions::AutomaticltInitializable::DigitalGpioCondition(digital_gpio_condition) => {
                         if digital_gpio_condition.is_output{
                             enviorment.add_output_gpio(digital_gpio_condition.pin)?;
                             continue;
                         }
-                        if !enviorment.input_gpios.contains_key(&digital_gpio_condition.pin) {
-                            enviorment.input_gpios.insert(
-                                digital_gpio_condition.pin,
-                                InputPinHandler {
-                                    handler: Gpio::new()
-                                        .map_err(|e| TaskError::IoError { comment: (format!("Could not access GPIOs: {}", e)) })?
-                                        .get(digital_gpio_condition.pin)
-                                        .map_err(|e| TaskError::IoError { comment: (format!("Could not get pin {}: {}", digital_gpio_condition.pin, e)) })?
-                                        .into_input_pullup(),
-                                    last_state: false,
-                                    current_state: false,
-                                    last_change: unix_now!(f64),
-                                },
-                            );
-                        }
+                        enviorment.add_input_gpio(digital_gpio_condition.pin)?;
+                    },
+                    crate::conditions::AutomaticltInitializable::InputPin(pin) => {
+                        enviorment.add_input_gpio(pin)?;
+                    },
+                    crate::conditions::AutomaticltInitializable::OutputPin(pin) => {
+                        enviorment.add_output_gpio(pin)?;
+                    },
+                    crate::conditions::AutomaticltInitializable::PulseCounter(pin) => {
+                        enviorment.add_input_gpio(pin)?;
+                        enviorment.enable_pulse_counting(pin)?;
                     },
                 }                    
             }
         }
-        enviorment.app_state.insert(format!("{}_executed", as_condtional.get_name()), StateType::Int(-1.));
+        enviorment.app_state.set_quiet(&format!("{}_executed", as_condtional.get_name()), StateType::Float(-1.));
+    }
+    if let ConditionalTypes::Task(task) = unit {
+        if task.timeout().is_some() {
+            enviorment.app_state.declare(&format!("{}_timed_out", task.get_name()), StateType::Bool(false))?;
+        }
+        if task.retry_policy().is_some() {
+            enviorment.app_state.declare(&format!("{}_attempts", task.get_name()), StateType::Int(0))?;
+            enviorment.app_state.declare(&format!("{}_last_error", task.get_name()), StateType::Str("".to_string()))?;
+        }
     }
         
     if let ConditionalTypes::TaskContext(task_context) = unit {
@@ -81,18 +96,28 @@ fn recursively_initialize(mut enviorment: Environment, unit: &ConditionalTypes)
 }
 
 impl Environment {
-    pub(super) fn new(tasks: &HashMap<&str, ConditionalTypes>, lcd_driver_path: Option<&PathBuf>, log_file: Option<PathBuf>) -> Result<Environment, TaskError> {
+    pub(super) fn new(tasks: &HashMap<&str, ConditionalTypes>, options: &SutieOptions) -> Result<Environment, TaskError> {
         let (tx, rx) = mpsc::channel();
+        let log_file = options.log_file.clone();
         thread::spawn(move ||{
             logger(rx,  log_file);
         });
+        let clock = options.clock.clone().unwrap_or_else(default_clock);
+        let events = EventNotifier::new();
         let mut enviorment = Environment {
             input_gpios: HashMap::new(),
-            app_state: HashMap::new(),
+            app_state: StateStore::new(clock.clone(), events.clone()),
             logger: tx,
             pid: std::process::id(),
             output_gpios: HashMap::new(),
-            lcd_driver: match lcd_driver_path {
+            gpio_backend: options.gpio_backend.clone().unwrap_or_else(default_backend),
+            clock,
+            output_pin_config: options.output_pin_config.clone(),
+            input_pin_config: options.input_pin_config.clone(),
+            safe_outputs: SafeOutputs::default(),
+            events,
+            tick: 0,
+            lcd_driver: match &options.lcd_driver {
                 Some(p) => LCDdriver::new(p, true).map_err(|_| p.clone()),
                 None => Err(PathBuf::new())
             }
@@ -102,35 +127,159 @@ impl Environment {
             enviorment = recursively_initialize(enviorment, unit)?;
         }
         
-        return Ok(enviorment)
+        Ok(enviorment)
+    }
+
+    pub(super) fn add_input_gpio(&mut self, pin: u8) -> Result<(), TaskError> {
+        if let Entry::Vacant(entry) = self.input_gpios.entry(pin) {
+            let now = self.clock.now();
+            let config = self.input_pin_config.get(&pin).copied().unwrap_or_default();
+            entry.insert(InputPinHandler {
+                handler: self.gpio_backend.open_input(pin, config.pull)?,
+                config,
+                edges: VecDeque::new(),
+                last_state: false,
+                current_state: false,
+                last_change: now,
+                raw_state: false,
+                raw_change: now,
+                changed_in_tick: 0,
+                pulses: None,
+            });
+            if self.input_pin_config.get(&pin).is_some_and(|config| config.count_pulses) {
+                self.enable_pulse_counting(pin)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Starts counting pulses of an opened input pin, using its interrupt if
+    /// the backend supports it. Declares the app_state keys of the counter.
+    pub(crate) fn enable_pulse_counting(&mut self, pin: u8) -> Result<(), TaskError> {
+        let now = self.now();
+        let handler = self.input_gpios.get_mut(&pin)
+            .ok_or(TaskError::IoError { comment: format!("Pin {} not found in input GPIO state", pin) })?;
+        if handler.pulses.is_some() {
+            return Ok(());
+        }
+        let mut counter = PulseCounter::new(now);
+        let count = counter.count.clone();
+        let active_low = handler.config.active_low;
+        match handler.handler.set_interrupt(Box::new(move |high| if high != active_low {
+            count.fetch_add(1, Ordering::Relaxed);
+        })) {
+            Ok(()) => counter.interrupts = true,
+            Err(error) => {
+                let _ = self.logger.send(LoggerCommand::Log(format!("Counting pulses of pin {} when polling: {}", pin, error), LogLevel::Warning));
+            },
+        }
+        handler.pulses = Some(counter);
+        self.app_state.declare(&pulse_count_key(pin), StateType::Int(0))?;
+        self.app_state.declare(&pulse_rate_key(pin), StateType::Float(0.))
+    }
+
+    /// Adds the pulses counted since the last tick to app_state and updates
+    /// the rates. Bookkeeping like `_executed`, it does not wake the dispatcher.
+    pub(crate) fn record_pulses(&mut self) {
+        for (pin, handler) in self.input_gpios.iter_mut() {
+            let Some(counter) = handler.pulses.as_mut() else { continue };
+            let new = counter.take_new();
+            if new > 0 {
+                let key = pulse_count_key(*pin);
+                let total = self.app_state.get(&key).map_or(0, StateType::as_int);
+                self.app_state.set_quiet(&key, StateType::Int(total + new as i64));
+            }
+            let key = pulse_rate_key(*pin);
+            if self.app_state.get(&key) != Some(&StateType::Float(counter.rate)) {
+                self.app_state.set_quiet(&key, StateType::Float(counter.rate));
+            }
+        }
+    }
+
+    /// Resets the pulse counter of `pin` to 0.
+    pub fn reset_pulses(&mut self, pin: u8) -> Result<(), TaskError> {
+        self.app_state.set(&pulse_count_key(pin), StateType::Int(0))
+    }
+
+    /// Seconds until the earliest pending input change settles.
+    pub(crate) fn next_input_settle(&self) -> Option<f64> {
+        let now = self.now();
+        self.input_gpios.values()
+            .filter_map(|handler| handler.settles_in(now))
+            .reduce(f64::min)
     }
 
     pub(super) fn add_output_gpio(&mut self, pin: u8) -> Result<(), TaskError> {
         if self.output_gpios.contains_key(&pin) {
             return Ok(());
         }
-        let mut gpio = Gpio::new()
-            .map_err(|e| TaskError::IoError { comment: (format!("Could not access GPIOs: {}", e)) })?
-            .get(pin)
-            .map_err(|e| TaskError::IoError { comment: (format!("Could not get pin {}: {}", pin, e)) })?
-            .into_output();
-        gpio.set_high();
+        let config = self.output_pin_config.get(&pin).copied().unwrap_or_default();
+        let initial_state = config.safe_state.unwrap_or(false);
+        let mut gpio = self.gpio_backend.open_output(pin)?;
+        gpio.write(config.level(initial_state));
+        let gpio = Arc::new(Mutex::new(gpio));
+        if let Some(safe_state) = config.safe_state {
+            self.safe_outputs.register(config.level(safe_state), gpio.clone());
+        }
 
         let new_handler = OutputPinHandler {
             handler: gpio,
-            last_state: false,
-            current_state: false,
-            last_change: unix_now!(f64),
+            clock: self.clock.clone(),
+            config,
+            last_state: initial_state,
+            current_state: initial_state,
+            last_change: self.now(),
+            sampled_state: initial_state,
+            changed_in_tick: 0,
         };
 
         self.output_gpios.insert(pin, new_handler);
         Ok(())
     }
 
-    pub fn log(&self, msg: &str, log_level: LogLevel) -> (){
+    /// Registers interrupts on all input pins which notify the event driven
+    /// dispatcher. Pins of backends without interrupt support stay polled, as
+    /// do pins counting pulses, which would wake the dispatcher on every pulse.
+    pub(crate) fn enable_interrupts(&mut self) {
+        for (pin, handler) in self.input_gpios.iter_mut().filter(|(_, handler)| handler.pulses.is_none()) {
+            let events = self.events.clone();
+            if let Err(error) = handler.handler.set_interrupt(Box::new(move |_| events.notify())) {
+                let _ = self.logger.send(LoggerCommand::Log(format!("Polling pin {}: {}", pin, error), LogLevel::Debug));
+            }
+        }
+    }
+
+    pub fn event_notifier(&self) -> EventNotifier {
+        self.events.clone()
+    }
+
+    /// Drives all output pins with a declared safe state to it.
+    pub fn drive_safe_state(&mut self) {
+        for (pin, config) in self.output_pin_config.iter() {
+            if let (Some(state), Some(handler)) = (config.safe_state, self.output_gpios.get_mut(pin)) {
+                handler.change_state(state);
+            }
+        }
+        self.safe_outputs.drive();
+    }
+
+    /// Number of the current dispatcher tick.
+    pub fn current_tick(&self) -> u64 {
+        self.tick
+    }
+
+    /// Current time of the suite clock in seconds since the unix epoch.
+    pub fn now(&self) -> f64 {
+        self.clock.now()
+    }
+    pub fn clock(&self) -> Arc<dyn Clock> {
+        self.clock.clone()
+    }
+
+    pub fn log(&self, msg: &str, log_level: LogLevel) {
         let _ = self.logger.send(LoggerCommand::Log(msg.to_string(), log_level));
     }
-    pub fn change_log_level(&self, log_level: LogLevel) -> (){
+    pub fn change_log_level(&self, log_level: LogLevel) {
         let _ = self.logger.send(LoggerCommand::ChangeLogLevel(log_level));
     }
 }
@@ -143,7 +292,7 @@ impl fmt::Display for Environment {
             environment: HashMap<String, HashMap<String, String>>,
         }
         let mut print_env = EnviormentDisplay {
-            time: unix_now!(),
+            time: self.now() as u64,
             environment: HashMap::new(),
         };
         let mut gpio_state_print = HashMap::new();
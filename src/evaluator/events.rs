@@ -0,0 +1,43 @@
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+
+/// Wakes the event driven dispatcher. Input interrupts, state changes and
+/// finished tasks notify it; external code can do the same after changing
+/// something a condition depends on. Clones share the same state.
+#[derive(Clone, Debug, Default)]
+pub struct EventNotifier {
+    pending: Arc<(Mutex<bool>, Condvar)>,
+    #[cfg(feature = "async")]
+    notify: Arc<tokio::sync::Notify>,
+}
+
+impl EventNotifier {
+    pub fn new() -> EventNotifier {
+        EventNotifier::default()
+    }
+
+    pub fn notify(&self) {
+        let (pending, condvar) = &*self.pending;
+        *pending.lock().unwrap_or_else(|e| e.into_inner()) = true;
+        condvar.notify_all();
+        #[cfg(feature = "async")]
+        self.notify.notify_one();
+    }
+
+    /// Waits up to `timeout` for an event, returns whether one happened.
+    pub(crate) fn wait(&self, timeout: Duration) -> bool {
+        let (pending, condvar) = &*self.pending;
+        let guard = pending.lock().unwrap_or_else(|e| e.into_inner());
+        let (mut guard, _) = condvar.wait_timeout_while(guard, timeout, |pending| !*pending)
+            .unwrap_or_else(|e| e.into_inner());
+        std::mem::replace(&mut *guard, false)
+    }
+
+    #[cfg(feature = "async")]
+    pub(crate) async fn wait_async(&self, timeout: Duration) -> bool {
+        let happened = tokio::time::timeout(timeout, self.notify.notified()).await.is_ok();
+        *self.pending.0.lock().unwrap_or_else(|e| e.into_inner()) = false;
+        happened
+    }
+}
@@ -18,6 +18,7 @@ pub enum LogLevel {
     Error = 3,
 }
 impl LogLevel {
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(level: &str) -> Option<LogLevel> {
         match level.to_uppercase().as_str() {
             "DEBUG" => Some(LogLevel::Debug),
@@ -49,7 +50,6 @@ impl std::fmt::Display for LogLevel {
 
 pub(super) fn logger(rx: Receiver<LoggerCommand>, log_file: Option<PathBuf>) {
     let stdout = io::stdout(); // Get the stdout handle
-    let mut handle = stdout.lock(); // Lock it here inside the loop
     let mut log_level = LogLevel::Debug; // Default log level
     loop {
         let (to_write, write_level) = match rx.recv() {
@@ -60,36 +60,33 @@ pub(super) fn logger(rx: Receiver<LoggerCommand>, log_file: Option<PathBuf>) {
                 log_level = new_level; // Change the log level
                 (format!("Log level changed to: {}", new_level), LogLevel::Info)
             }
-            Err(e) => {
-                (format!("Error receiving log message: {}", e), LogLevel::Error)
-            } 
+            Err(_) => break, // Environment was dropped
         };
-        if to_write != "" {
-            if log_level as u8 <= write_level as u8 {
-                writeln!(handle, "{}", to_write).unwrap();
-                if let Some(ref path) = log_file {
-                    let file = fs::OpenOptions::new()
-                        .open(path);
-                    if file.is_err() {
-                        writeln!(handle, "ERROR: Could not write to log file").unwrap();
-                    }
+        if !to_write.is_empty() && log_level as u8 <= write_level as u8 {
+            let mut handle = stdout.lock(); // Lock it here inside the loop
+            writeln!(handle, "{}", to_write).unwrap();
+            if let Some(ref path) = log_file {
+                let file = fs::OpenOptions::new()
+                    .open(path);
+                if file.is_err() {
+                    writeln!(handle, "ERROR: Could not write to log file").unwrap();
+                }
 
 
-                    let reader: io::BufReader<_> = io::BufReader::new(file.expect(""));
-                    let file = match  reader.lines().count() > 1000 {
-                        true => fs::OpenOptions::new()
-                            .write(true)
-                            .truncate(true)
-                            .open(path),
-                        false => fs::OpenOptions::new()
-                            .append(true)
-                            .create(true)
-                            .open(path),
-                    };
+                let reader: io::BufReader<_> = io::BufReader::new(file.expect(""));
+                let file = match  reader.lines().count() > 1000 {
+                    true => fs::OpenOptions::new()
+                        .write(true)
+                        .truncate(true)
+                        .open(path),
+                    false => fs::OpenOptions::new()
+                        .append(true)
+                        .create(true)
+                        .open(path),
+                };
 
-                    let _ = writeln!(file.expect(""), "{}", to_write);
-                    }
-                }
+                let _ = writeln!(file.expect(""), "{}", to_write);
             }
         }
     }
+}
\ No newline at end of file
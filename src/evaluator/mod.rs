@@ -6,6 +6,13 @@ pub mod enviorment;
 pub mod suite;
 pub mod dispatcher;
 pub mod logger;
+pub mod shutdown;
+pub mod worker_pool;
+pub mod events;
+pub mod state_store;
+pub mod persistence;
+#[cfg(feature = "async")]
+pub mod async_dispatcher;
 
 use crate::tasks::ConditionalTypes;
 
@@ -28,7 +35,7 @@ impl RunningTreeState {
             currently_active: true,
         }
     }
-    pub(crate) fn get_running_tree_for_subtask<'a>(&'a self) -> RunningTreeState {
+    pub(crate) fn get_running_tree_for_subtask(&self) -> RunningTreeState {
         RunningTreeState {
             moved_in_from_back: false,
             first_iteration_after_move: self.first_iteration_after_move,
@@ -0,0 +1,192 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use serde_json::{Map, Value as JsonValue};
+
+use crate::errors::TaskError;
+use super::enviorment::Environment;
+use super::logger::LogLevel;
+use super::suite::json_config_loader;
+
+
+#[derive(Clone, Debug)]
+pub enum PersistedKeys {
+    Keys(Vec<String>),
+    Prefix(String),
+}
+
+impl PersistedKeys {
+    fn matches(&self, key: &str) -> bool {
+        match self {
+            PersistedKeys::Keys(keys) => keys.iter().any(|persisted| persisted == key),
+            PersistedKeys::Prefix(prefix) => key.starts_with(prefix.as_str()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SaveMode {
+    OnChange,
+    Interval(f64), // seconds, only saves if something changed
+}
+
+/// Keeps selected app_state keys in a JSON file across restarts.
+#[derive(Clone, Debug)]
+pub struct PersistenceOptions {
+    pub path: PathBuf,
+    pub keys: PersistedKeys,
+    pub save: SaveMode,
+}
+
+impl PersistenceOptions {
+    pub fn new(path: PathBuf, keys: PersistedKeys) -> PersistenceOptions {
+        PersistenceOptions { path, keys, save: SaveMode::OnChange }
+    }
+    pub fn every(mut self, seconds: f64) -> PersistenceOptions {
+        self.save = SaveMode::Interval(seconds);
+        self
+    }
+}
+
+pub(crate) struct Persistence {
+    options: PersistenceOptions,
+    dirty: Arc<AtomicBool>,
+    last_save: f64,
+    restored: HashSet<String>,
+}
+
+impl Persistence {
+    pub(crate) fn new(options: PersistenceOptions) -> Persistence {
+        Persistence { options, dirty: Arc::new(AtomicBool::new(false)), last_save: 0., restored: HashSet::new() }
+    }
+
+    /// Whether `key` got its value from the persisted file, the config does not override those.
+    pub(crate) fn was_restored(&self, key: &str) -> bool {
+        self.restored.contains(key)
+    }
+
+    /// Restores the persisted keys and starts tracking changes of them.
+    /// A missing or broken file only gets logged, the suite starts with defaults.
+    pub(crate) fn attach(&mut self, environment: &mut Environment) {
+        if let Err(error) = self.restore(environment) {
+            environment.log(&format!("Could not restore persisted state: {}", error), LogLevel::Error);
+        }
+        let keys = self.options.keys.clone();
+        let dirty = self.dirty.clone();
+        environment.app_state.subscribe_all(Box::new(move |key, _, _| {
+            if keys.matches(key) {
+                dirty.store(true, Ordering::SeqCst);
+            }
+        }));
+        self.last_save = environment.now();
+    }
+
+    fn restore(&mut self, environment: &mut Environment) -> Result<(), TaskError> {
+        let raw = match fs::read_to_string(&self.options.path) {
+            Ok(raw) => raw,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(TaskError::IoError { comment: format!("Could not read {}: {}", self.options.path.display(), e) }),
+        };
+        let persisted: JsonValue = serde_json::from_str(&raw)
+            .map_err(|e| TaskError::SystemError { comment: format!("Could not parse {}: {}", self.options.path.display(), e) })?;
+        let mut state = HashMap::new();
+        json_config_loader(&mut state, persisted)?;
+        for (key, value) in state.into_iter().filter(|(key, _)| self.options.keys.matches(key)) {
+            match environment.app_state.set(&key, value) {
+                Ok(()) => { self.restored.insert(key); },
+                Err(error) => environment.log(&format!("Not restoring {}: {}", key, error), LogLevel::Warning),
+            }
+        }
+        Ok(())
+    }
+
+    /// Saves if something changed and, in interval mode, the interval passed.
+    pub(crate) fn save_if_due(&mut self, environment: &Environment) -> Result<(), TaskError> {
+        let due = match self.options.save {
+            SaveMode::OnChange => true,
+            SaveMode::Interval(interval) => environment.now() - self.last_save >= interval,
+        };
+        if due && self.dirty.load(Ordering::SeqCst) {
+            self.save(environment)?;
+        }
+        Ok(())
+    }
+
+    /// Writes to a temporary file first and renames it, so a crash while
+    /// saving never leaves a truncated file behind. Both the file and the
+    /// rename are synced, a power cut right after saving keeps the new state.
+    pub(crate) fn save(&mut self, environment: &Environment) -> Result<(), TaskError> {
+        self.dirty.store(false, Ordering::SeqCst);
+        self.last_save = environment.now();
+        let persisted: Map<String, JsonValue> = environment.app_state.iter()
+            .filter(|(key, _)| self.options.keys.matches(key))
+            .map(|(key, value)| (key.clone(), serde_json::to_value(value).unwrap_or(JsonValue::Null)))
+            .collect();
+        let mut temporary = self.options.path.clone().into_os_string();
+        temporary.push(".tmp");
+        let directory = match self.options.path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let write = File::create(&temporary)
+            .and_then(|mut file| {
+                file.write_all(JsonValue::Object(persisted).to_string().as_bytes())?;
+                file.sync_all()
+            })
+            .and_then(|_| fs::rename(&temporary, &self.options.path))
+            .and_then(|_| File::open(directory)?.sync_all());
+        if let Err(e) = write {
+            self.dirty.store(true, Ordering::SeqCst);
+            return Err(TaskError::IoError { comment: format!("Could not persist state to {}: {}", self.options.path.display(), e) });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluator::suite::{Suite, SutieOptions};
+    use crate::testing::{temporary_path, Simulation};
+    use crate::types::StateType;
+
+    fn restart<'a>(options: &SutieOptions) -> Suite<'a> {
+        let suite = Suite::new(HashMap::new(), None, Some(options.clone())).unwrap();
+        suite.load_config(None).unwrap();
+        suite
+    }
+
+    #[test]
+    fn persisted_keys_survive_restarts() {
+        let simulation = Simulation::new();
+        let (path, config) = (temporary_path("persisted.json"), temporary_path("persisted_config.json"));
+        fs::write(&config, r#"{"brightness": 10, "volume": 3}"#).unwrap();
+        let mut options = simulation.options();
+        options.config_file = Some(config.clone());
+        options.persistence = Some(PersistenceOptions::new(path.clone(), PersistedKeys::Keys(vec!["brightness".to_string()])));
+
+        let mut suite = restart(&options);
+        suite.environment().write().unwrap().app_state.set("brightness", StateType::Int(70)).unwrap();
+        suite.environment().write().unwrap().app_state.set("volume", StateType::Int(8)).unwrap();
+        simulation.tick_at(&mut suite, 1.);
+        assert!(!fs::read_to_string(&path).unwrap().contains("volume"));
+
+        // The restored value wins over the config, other keys come from the config
+        let mut suite = restart(&options);
+        let environment = suite.environment();
+        assert_eq!(environment.read().unwrap().app_state.get("brightness"), Some(&StateType::Int(70)));
+        assert_eq!(environment.read().unwrap().app_state.get("volume"), Some(&StateType::Int(3)));
+
+        environment.write().unwrap().app_state.set("brightness", StateType::Int(20)).unwrap();
+        simulation.tick_at(&mut suite, 2.);
+        let suite = restart(&options);
+        assert_eq!(suite.environment().read().unwrap().app_state.get("brightness"), Some(&StateType::Int(20)));
+
+        fs::remove_file(path).unwrap();
+        fs::remove_file(config).unwrap();
+    }
+}
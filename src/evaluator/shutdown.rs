@@ -0,0 +1,88 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+
+static SIGNAL_RECEIVED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_signal(_: libc::c_int) {
+    SIGNAL_RECEIVED.store(true, Ordering::SeqCst);
+}
+
+/// Handlers of the host program, put back when dropped.
+pub(crate) struct PreviousSignalHandlers {
+    interrupt: libc::sigaction,
+    terminate: libc::sigaction,
+}
+
+impl Drop for PreviousSignalHandlers {
+    fn drop(&mut self) {
+        unsafe {
+            libc::sigaction(libc::SIGINT, &self.interrupt, std::ptr::null_mut());
+            libc::sigaction(libc::SIGTERM, &self.terminate, std::ptr::null_mut());
+        }
+    }
+}
+
+/// Installs handlers for SIGINT and SIGTERM which only set a flag,
+/// the dispatcher picks it up on its next tick. A signal which stopped
+/// an earlier dispatcher in the same process is forgotten. The previous
+/// handlers are restored once the returned value is dropped.
+pub(crate) fn install_signal_handlers() -> PreviousSignalHandlers {
+    SIGNAL_RECEIVED.store(false, Ordering::SeqCst);
+    unsafe {
+        // sigaction instead of signal, whose semantics differ between platforms
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+        let mut previous = PreviousSignalHandlers { interrupt: std::mem::zeroed(), terminate: std::mem::zeroed() };
+        libc::sigaction(libc::SIGINT, &action, &mut previous.interrupt);
+        libc::sigaction(libc::SIGTERM, &action, &mut previous.terminate);
+        previous
+    }
+}
+
+pub(crate) fn signal_received() -> bool {
+    SIGNAL_RECEIVED.load(Ordering::SeqCst)
+}
+
+/// Requests a running suite to stop. Can be cloned and moved to other threads.
+#[derive(Clone, Debug, Default)]
+pub struct ShutdownHandle {
+    requested: Arc<AtomicBool>,
+}
+
+impl ShutdownHandle {
+    pub fn new() -> ShutdownHandle {
+        ShutdownHandle::default()
+    }
+    pub fn shutdown(&self) {
+        self.requested.store(true, Ordering::SeqCst);
+    }
+    pub fn is_shutdown_requested(&self) -> bool {
+        self.requested.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn current_handler(signal: libc::c_int) -> libc::sighandler_t {
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            libc::sigaction(signal, std::ptr::null(), &mut action);
+            action.sa_sigaction
+        }
+    }
+
+    #[test]
+    fn previous_handlers_are_restored() {
+        let (interrupt, terminate) = (current_handler(libc::SIGINT), current_handler(libc::SIGTERM));
+        let previous = install_signal_handlers();
+        assert_eq!(current_handler(libc::SIGINT), on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t);
+        drop(previous);
+        assert_eq!(current_handler(libc::SIGINT), interrupt);
+        assert_eq!(current_handler(libc::SIGTERM), terminate);
+    }
+}
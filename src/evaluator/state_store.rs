@@ -0,0 +1,241 @@
+use core::fmt;
+use std::collections::HashMap;
+use std::mem::{self, Discriminant};
+use std::sync::Arc;
+
+use crate::clock::Clock;
+use crate::errors::TaskError;
+use crate::types::StateType;
+use super::events::EventNotifier;
+
+
+/// Called with the key, the new and the previous value after a value changed.
+/// Runs while the environment is locked for writing and must not lock it again.
+pub type StateSubscriber = Box<dyn FnMut(&str, &StateType, Option<&StateType>) + Send + Sync>;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct SubscriptionId(usize);
+
+fn int_kind() -> Discriminant<StateType> {
+    mem::discriminant(&StateType::Int(0))
+}
+
+fn float_kind() -> Discriminant<StateType> {
+    mem::discriminant(&StateType::Float(0.))
+}
+
+fn is_number(kind: Discriminant<StateType>) -> bool {
+    kind == int_kind() || kind == float_kind()
+}
+
+#[derive(Clone, Debug)]
+struct StateEntry {
+    value: StateType,
+    changed_at: f64,
+}
+
+/// Application state shared by tasks and conditions. Keys can be declared
+/// with a type, after which writing a value of another type is an error.
+/// `Int` and `Float` count as one numeric type, values are converted to
+/// the declared one, so `25` in a config fits a key declared as `Float`.
+/// Every change is timestamped, wakes the event driven dispatcher and is
+/// passed to the subscribers of the key.
+pub struct StateStore {
+    values: HashMap<String, StateEntry>,
+    types: HashMap<String, Discriminant<StateType>>,
+    subscribers: Vec<(SubscriptionId, Option<String>, StateSubscriber)>, // None subscribes to all keys
+    next_subscription: usize,
+    clock: Arc<dyn Clock>,
+    events: EventNotifier,
+}
+
+impl StateStore {
+    pub(crate) fn new(clock: Arc<dyn Clock>, events: EventNotifier) -> StateStore {
+        StateStore {
+            values: HashMap::new(),
+            types: HashMap::new(),
+            subscribers: Vec::new(),
+            next_subscription: 0,
+            clock,
+            events,
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&StateType> {
+        self.values.get(key).map(|entry| &entry.value)
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.values.contains_key(key)
+    }
+
+    /// Time of the last change of `key` on the suite clock.
+    pub fn changed_at(&self, key: &str) -> Option<f64> {
+        self.values.get(key).map(|entry| entry.changed_at)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &StateType)> {
+        self.values.iter().map(|(key, entry)| (key, &entry.value))
+    }
+
+    /// Copy of all values, without types and timestamps.
+    pub fn to_map(&self) -> HashMap<String, StateType> {
+        self.iter().map(|(key, value)| (key.clone(), value.clone())).collect()
+    }
+
+    /// Fixes the type of `key` to the type of `default`, which is set if the
+    /// key has no value yet. Fails if the key was declared or holds a value
+    /// of another type. A key declared as both `Int` and `Float` holds floats.
+    pub fn declare(&mut self, key: &str, default: StateType) -> Result<(), TaskError> {
+        let mut kind = mem::discriminant(&default);
+        match self.types.get(key) {
+            // Compared as Int by one condition and as Float by another
+            Some(declared) if *declared != kind && is_number(*declared) && is_number(kind) => kind = float_kind(),
+            Some(declared) if *declared != kind => return Err(TaskError::StateError {
+                comment: format!("Key {} is already declared with another type than {:?}", key, default) }),
+            _ => {},
+        }
+        let value = Self::convert(key, self.get(key).cloned().unwrap_or(default), kind)?;
+        self.types.insert(key.to_string(), kind);
+        if self.get(key) != Some(&value) {
+            self.set_quiet(key, value);
+        }
+        Ok(())
+    }
+
+    /// Fails if `key` is declared with another type than `value`.
+    pub fn check_type(&self, key: &str, value: &StateType) -> Result<(), TaskError> {
+        self.typed(key, value.clone()).map(|_| ())
+    }
+
+    /// `value` converted to the declared type of `key`.
+    fn typed(&self, key: &str, value: StateType) -> Result<StateType, TaskError> {
+        match self.types.get(key) {
+            Some(declared) => Self::convert(key, value, *declared),
+            None => Ok(value),
+        }
+    }
+
+    fn convert(key: &str, value: StateType, kind: Discriminant<StateType>) -> Result<StateType, TaskError> {
+        let converted = match value {
+            StateType::Int(int) if kind == float_kind() => StateType::Float(int as f64),
+            // Only whole numbers, anything else would lose precision
+            StateType::Float(float) if kind == int_kind() && float.fract() == 0. => StateType::Int(float as i64),
+            value => value,
+        };
+        if mem::discriminant(&converted) != kind {
+            return Err(TaskError::StateError {
+                comment: format!("Key {} does not accept {:?}, it is declared with another type", key, converted) });
+        }
+        Ok(converted)
+    }
+
+    /// Sets `key`, notifying subscribers and the dispatcher if the value changed.
+    pub fn set(&mut self, key: &str, value: StateType) -> Result<(), TaskError> {
+        let value = self.typed(key, value)?;
+        if self.get(key) == Some(&value) {
+            return Ok(());
+        }
+        let previous = self.set_quiet(key, value.clone());
+        for (_, subscribed, subscriber) in self.subscribers.iter_mut() {
+            if subscribed.as_deref().is_none_or(|subscribed| subscribed == key) {
+                subscriber(key, &value, previous.as_ref());
+            }
+        }
+        self.events.notify();
+        Ok(())
+    }
+
+    /// Sets `key` to the result of `f` applied to its current value.
+    pub fn update<F: FnOnce(Option<&StateType>) -> StateType>(&mut self, key: &str, f: F) -> Result<(), TaskError> {
+        let value = f(self.get(key));
+        self.set(key, value)
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<StateType> {
+        self.values.remove(key).map(|entry| entry.value)
+    }
+
+    /// Bookkeeping writes of the dispatcher, which must not wake it again.
+    pub(crate) fn set_quiet(&mut self, key: &str, value: StateType) -> Option<StateType> {
+        let changed_at = self.clock.now();
+        self.values.insert(key.to_string(), StateEntry { value, changed_at })
+            .map(|entry| entry.value)
+    }
+
+    pub fn subscribe(&mut self, key: &str, subscriber: StateSubscriber) -> SubscriptionId {
+        self.add_subscriber(Some(key.to_string()), subscriber)
+    }
+
+    pub fn subscribe_all(&mut self, subscriber: StateSubscriber) -> SubscriptionId {
+        self.add_subscriber(None, subscriber)
+    }
+
+    fn add_subscriber(&mut self, key: Option<String>, subscriber: StateSubscriber) -> SubscriptionId {
+        let id = SubscriptionId(self.next_subscription);
+        self.next_subscription += 1;
+        self.subscribers.push((id, key, subscriber));
+        id
+    }
+
+    pub fn unsubscribe(&mut self, id: SubscriptionId) {
+        self.subscribers.retain(|(subscription, _, _)| *subscription != id);
+    }
+}
+
+impl fmt::Debug for StateStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StateStore")
+            .field("values", &self.values)
+            .field("declared", &self.types.keys().collect::<Vec<_>>())
+            .field("subscribers", &self.subscribers.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use super::*;
+    use crate::conditions::app_state::AppCondition;
+    use crate::evaluator::suite::Suite;
+    use crate::testing::{counting_task, temporary_path, Simulation};
+
+    #[test]
+    fn declared_types_are_enforced_in_a_running_suite() {
+        let simulation = Simulation::new();
+        let config = temporary_path("typed_config.json");
+        fs::write(&config, r#"{"mode": 3, "label": "kitchen"}"#).unwrap();
+        let mut options = simulation.options();
+        options.config_file = Some(config.clone());
+        let automatic = Arc::new(AtomicU32::new(0));
+        let tasks = HashMap::from([("mode", vec![counting_task("automatic", &automatic)
+            .when_condition(AppCondition::new("mode", StateType::Str("auto".to_string())))
+            .to_eveluatable()])]);
+        let mut suite = Suite::new(tasks, None, Some(options)).unwrap();
+        let environment = suite.environment();
+
+        // A config with a wrong type is not applied at all
+        assert!(suite.load_config(None).is_err());
+        assert_eq!(environment.read().unwrap().app_state.get("label"), None);
+        assert!(environment.write().unwrap().app_state.set("mode", StateType::Int(1)).is_err());
+        assert_eq!(environment.read().unwrap().app_state.get("mode"), Some(&StateType::Str("".to_string())));
+
+        let changes = Arc::new(AtomicU32::new(0));
+        let counter = changes.clone();
+        environment.write().unwrap().app_state.subscribe("mode", Box::new(move |_, _, _| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        }));
+        simulation.clock.set(2.);
+        environment.write().unwrap().app_state.set("mode", StateType::Str("auto".to_string())).unwrap();
+        environment.write().unwrap().app_state.set("mode", StateType::Str("auto".to_string())).unwrap();
+        assert_eq!(changes.load(Ordering::SeqCst), 1);
+        assert_eq!(environment.read().unwrap().app_state.changed_at("mode"), Some(2.));
+
+        simulation.tick_at(&mut suite, 2.5);
+        assert_eq!(automatic.load(Ordering::SeqCst), 1);
+        fs::remove_file(config).unwrap();
+    }
+}
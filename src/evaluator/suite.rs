@@ -9,31 +9,63 @@ use serde_json::Value as JsonValue;
 
 use crate::conditions::constants::AllwaysTrue;
 use crate::errors::TaskError;
-use crate::types::StateType;
+use crate::types::{StateType, OutputPinConfig, InputPinConfig};
+use crate::gpio::GpioBackend;
+use crate::clock::Clock;
 use crate::tasks::{general_task::get_periodic_state_writer, ConditionalTypes};
 use crate::tasks::task_context::Unit;
 use crate::evaluator::enviorment::{Environment};
 use super::logger::LogLevel;
+use super::RunningTreeState;
+use super::dispatcher::RunningTask;
+use super::shutdown::ShutdownHandle;
+use super::worker_pool::{WorkerPool, WorkerPoolOptions};
+use super::persistence::{Persistence, PersistenceOptions};
 
 
 
+pub type ConfigHandler = fn(&mut HashMap<String, StateType>, JsonValue) -> Result<(), TaskError>;
+
 #[derive(Clone)]
 pub struct SutieOptions {
     pub periodicly_print_state_to_file: Option<u64>,
-    pub sleep_time: Option<u64>,
+    pub sleep_time: Option<u64>, // nanoseconds between ticks
     pub log_file: Option<PathBuf>,
     pub log_level: LogLevel,
     pub ignore_errors_when_possible: bool, // Not implemented yet
     pub config_file: Option<PathBuf>, 
-    pub lcd_driver: Option<PathBuf>
+    pub lcd_driver: Option<PathBuf>,
+    pub gpio_backend: Option<Arc<dyn GpioBackend>>,
+    pub clock: Option<Arc<dyn Clock>>,
+    pub handle_signals: bool, // SIGINT and SIGTERM stop suite_dispatcher gracefully
+    pub shutdown_grace_period: f64, // seconds to wait for running tasks on shutdown
+    pub output_pin_config: HashMap<u8, OutputPinConfig>, // pins listed here are initialized as outputs
+    pub input_pin_config: HashMap<u8, InputPinConfig>, // pins listed here are initialized as inputs
+    pub worker_pool: Option<WorkerPoolOptions>, // None spawns a thread per task execution
+    pub event_driven: bool, // evaluate on interrupts and state changes instead of every sleep_time
+    pub fallback_tick: f64, // seconds between evaluations without events, in event driven mode
+    pub persistence: Option<PersistenceOptions>, // app_state keys restored on start and saved on change
 }
 
 pub struct Suite<'a> {
     pub(crate) structure: Arc<RwLock<Environment>>,
     pub(crate) tasks: HashMap<&'a str, ConditionalTypes>,
     pub(crate) config_path: Option<PathBuf>,
-    pub(crate) suite_options: SutieOptions
+    pub(crate) suite_options: SutieOptions,
+    pub(crate) running_tasks: HashMap<String, RunningTask>,
+    pub(crate) active_units: HashMap<&'a str, (Vec<ConditionalTypes>, RunningTreeState)>,
+    pub(crate) shutdown: ShutdownHandle,
+    pub(crate) worker_pool: Option<WorkerPool>,
+    pub(crate) persistence: Option<Persistence>,
+    #[cfg(feature = "async")]
+    pub(crate) runtime: Option<tokio::runtime::Handle>, // set while run by the async dispatcher
+}
+impl Default for SutieOptions {
+    fn default() -> Self {
+        Self::new()
+    }
 }
+
 impl SutieOptions {
     pub fn new() -> SutieOptions {
         SutieOptions {
@@ -44,40 +76,39 @@ impl SutieOptions {
             ignore_errors_when_possible: false,
             config_file: None,
             lcd_driver: None,
+            gpio_backend: None,
+            clock: None,
+            handle_signals: true,
+            shutdown_grace_period: 5.,
+            output_pin_config: HashMap::new(),
+            input_pin_config: HashMap::new(),
+            worker_pool: None,
+            event_driven: false,
+            fallback_tick: 1.,
+            persistence: None,
         }
     }
 }
 
-fn json_config_loader(
+pub(crate) fn json_config_loader(
     app_state: &mut HashMap<String, StateType>,
     config: JsonValue,
 ) -> Result<(), TaskError> {
     if let JsonValue::Object(content) = config {
         for (key, item) in content {
-            match item {
-                JsonValue::String(value) => {
-                    app_state.insert(key, StateType::Str(value));
-                }
-                JsonValue::Number(value) => {
-                    app_state.insert(key, StateType::Int(value.as_f64().unwrap_or(0.)));
-                }
-                JsonValue::Bool(value) => {
-                    app_state.insert(key, StateType::Bool(value));
-                }
-                _ => {
-                    return Err(TaskError::SystemError { comment: "Unsupported JSON value type".to_string() });
-                }
-            }
-            }
-        } else {
-        return Err(TaskError::SystemError { comment: "Invalid JSON format; Only OBJECT.<key> = str|int|bool".to_string() });
+            let value = serde_json::from_value(item)
+                .map_err(|e| TaskError::SystemError { comment: format!("Unsupported JSON value for {}: {}", key, e) })?;
+            app_state.insert(key, value);
+        }
+    } else {
+        return Err(TaskError::SystemError { comment: "Invalid JSON format; Only OBJECT.<key> = <value>".to_string() });
     }
     Ok(())
 }
 
 impl <'a> Suite<'a> {
     pub fn new(tasks: HashMap<&'a str, Vec<ConditionalTypes>>, output_gpio: Option<Vec<u8>>, options: Option<SutieOptions>) -> Result<Suite<'a>, TaskError> {
-        let optios = options.unwrap_or(SutieOptions::new());
+        let optios = options.unwrap_or_default();
         let options_ = optios.clone();
         let mut task_layers = HashMap::new();
         for (key, value) in tasks.into_iter() {
@@ -90,32 +121,79 @@ impl <'a> Suite<'a> {
                 on_exit: None
             })));
         }    
-        if optios.periodicly_print_state_to_file.is_some() {
+        if let Some(period) = optios.periodicly_print_state_to_file {
             task_layers.insert("periodic_print_state_to_file", 
                 ConditionalTypes::Task(
-                    get_periodic_state_writer(optios.periodicly_print_state_to_file.unwrap() as u32)));
+                    get_periodic_state_writer(period as u32)));
         }
 
         let structure = Arc::new(RwLock::new(
-            Environment::new(&task_layers, optios.lcd_driver.as_ref(), optios.log_file)?));
-        if let Some(output_gpio) = output_gpio {
-            for pin in output_gpio {
-                structure.write().unwrap()
-                    .add_output_gpio(pin)?;
-            }
+            Environment::new(&task_layers, &optios)?));
+        for pin in optios.input_pin_config.keys() {
+            structure.write().unwrap()
+                .add_input_gpio(*pin)?;
+        }
+        for pin in output_gpio.into_iter().flatten().chain(optios.output_pin_config.keys().copied()) {
+            structure.write().unwrap()
+                .add_output_gpio(pin)?;
+        }
+        let persistence = optios.persistence.clone().map(|options| {
+            let mut persistence = Persistence::new(options);
+            persistence.attach(&mut structure.write().unwrap());
+            persistence
+        });
+        if optios.event_driven {
+            structure.write().unwrap().enable_interrupts();
+        }
+        if !structure.read().unwrap().safe_outputs.is_empty() {
+            structure.read().unwrap().safe_outputs.install_panic_hook();
         }
 
         structure.write().unwrap().change_log_level(optios.log_level);
         structure.read().unwrap().log(&format!("Environment initialized with: {:#?}", structure.read().unwrap()), LogLevel::Debug);
+        let active_units = task_layers.iter()
+            .map(|(name, unit)| (*name, (vec![unit.clone()], RunningTreeState::new())))
+            .collect();
         Ok(Suite {
             structure,
             tasks: task_layers,
             config_path: optios.config_file,
             suite_options: options_,
+            running_tasks: HashMap::new(),
+            active_units,
+            shutdown: ShutdownHandle::new(),
+            worker_pool: optios.worker_pool.map(WorkerPool::new),
+            persistence,
+            #[cfg(feature = "async")]
+            runtime: None,
         })   
     }
 
-    pub fn load_config(&self, handler_fn: Option<fn(&mut HashMap<String, StateType>, JsonValue) -> Result<(), TaskError>>) 
+    pub fn environment(&self) -> Arc<RwLock<Environment>> {
+        self.structure.clone()
+    }
+
+    /// Names of the active units of `tree`, from the root to the innermost one.
+    pub fn active_path(&self, tree: &str) -> Option<Vec<String>> {
+        self.active_units.get(tree)
+            .map(|(stack, _)| stack.iter().map(|unit| unit.get_inner_conditional().get_name()).collect())
+    }
+
+    /// Name of the innermost active unit of `tree`.
+    pub fn active_unit(&self, tree: &str) -> Option<String> {
+        self.active_path(tree).and_then(|mut path| path.pop())
+    }
+
+    /// Handle to stop `run_until` and `suite_dispatcher` from another thread.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    pub fn is_task_running(&self, name: &str) -> bool {
+        self.running_tasks.contains_key(name)
+    }
+
+    pub fn load_config(&self, handler_fn: Option<ConfigHandler>) 
         -> Result<(), TaskError> {
         if let Some(path) = &self.config_path {
             let raw_config = fs::read_to_string(path)
@@ -123,12 +201,18 @@ impl <'a> Suite<'a> {
             let config: JsonValue = serde_json::from_str(&raw_config)
                 .map_err(|e| TaskError::SystemError { comment: (format!("Could not parse config file: {}", e)) })?;
             
-            let pre_handler_state = self.structure.read().unwrap().app_state.clone();
-            if let Err(e) = handler_fn.unwrap_or_else(|| json_config_loader)
-                (&mut self.structure.write().unwrap().app_state, config){
-                    self.structure.write().unwrap().app_state = pre_handler_state;
-                    return Err(e);
-                }
+            // The handler works on a copy, nothing is applied if it fails or writes a wrong type
+            let mut env = self.structure.write().unwrap();
+            let mut state = env.app_state.to_map();
+            handler_fn.unwrap_or(json_config_loader)(&mut state, config)?;
+            // Restored values are newer than the config
+            state.retain(|key, _| !self.persistence.as_ref().is_some_and(|persistence| persistence.was_restored(key)));
+            for (key, value) in state.iter() {
+                env.app_state.check_type(key, value)?;
+            }
+            for (key, value) in state {
+                env.app_state.set(&key, value)?;
+            }
         } else {
             return Err(TaskError::SystemError { comment: "No config path provided".to_string() });
         }
@@ -0,0 +1,158 @@
+use std::collections::VecDeque;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::thread::{self, JoinHandle};
+
+
+/// What happens to a triggered task when the queue of the pool is full.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum OverflowPolicy {
+    Drop,  // the oldest queued task is dropped to make room
+    Skip,  // the triggered task is not queued and may trigger again later
+    Block, // the dispatcher waits until there is room in the queue
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct WorkerPoolOptions {
+    pub size: usize,
+    pub queue_length: usize,
+    pub overflow: OverflowPolicy,
+}
+
+impl WorkerPoolOptions {
+    pub fn new(size: usize) -> WorkerPoolOptions {
+        WorkerPoolOptions { size, queue_length: size * 4, overflow: OverflowPolicy::Skip }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum JobState {
+    Queued = 0,
+    Running = 1,
+    Finished = 2,
+    Panicked = 3,
+    Dropped = 4,
+}
+
+#[derive(Debug)]
+pub(crate) struct JobStatus {
+    state: AtomicU8,
+}
+
+impl JobStatus {
+    pub(crate) fn new(state: JobState) -> Arc<JobStatus> {
+        Arc::new(JobStatus { state: AtomicU8::new(state as u8) })
+    }
+    pub(crate) fn set(&self, state: JobState) {
+        self.state.store(state as u8, Ordering::SeqCst);
+    }
+    pub(crate) fn get(&self) -> JobState {
+        match self.state.load(Ordering::SeqCst) {
+            0 => JobState::Queued,
+            1 => JobState::Running,
+            2 => JobState::Finished,
+            3 => JobState::Panicked,
+            _ => JobState::Dropped,
+        }
+    }
+    pub(crate) fn is_finished(&self) -> bool {
+        !matches!(self.get(), JobState::Queued | JobState::Running)
+    }
+}
+
+type Job = Box<dyn FnOnce() + Send>;
+
+struct Queue {
+    jobs: VecDeque<(Job, Arc<JobStatus>)>,
+    closed: bool,
+}
+
+struct Shared {
+    queue: Mutex<Queue>,
+    job_available: Condvar,
+    space_available: Condvar,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, Queue> {
+        self.queue.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Fixed set of threads executing task actions from a bounded queue.
+pub(crate) struct WorkerPool {
+    shared: Arc<Shared>,
+    options: WorkerPoolOptions,
+    _workers: Vec<JoinHandle<()>>,
+}
+
+impl WorkerPool {
+    pub(crate) fn new(options: WorkerPoolOptions) -> WorkerPool {
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(Queue { jobs: VecDeque::new(), closed: false }),
+            job_available: Condvar::new(),
+            space_available: Condvar::new(),
+        });
+        let workers = (0..options.size.max(1))
+            .map(|_| {
+                let shared = shared.clone();
+                thread::spawn(move || worker(shared))
+            })
+            .collect();
+        WorkerPool { shared, options, _workers: workers }
+    }
+
+    /// Queues `job`, returns None if it was skipped because the queue is full.
+    pub(crate) fn submit(&self, job: Job) -> Option<Arc<JobStatus>> {
+        let status = JobStatus::new(JobState::Queued);
+        let mut queue = self.shared.lock();
+        while queue.jobs.len() >= self.options.queue_length.max(1) {
+            match self.options.overflow {
+                OverflowPolicy::Skip => return None,
+                OverflowPolicy::Drop => {
+                    if let Some((_, dropped)) = queue.jobs.pop_front() {
+                        dropped.set(JobState::Dropped);
+                    }
+                },
+                OverflowPolicy::Block => {
+                    queue = self.shared.space_available.wait(queue).unwrap_or_else(|e| e.into_inner());
+                },
+            }
+        }
+        queue.jobs.push_back((job, status.clone()));
+        self.shared.job_available.notify_one();
+        Some(status)
+    }
+}
+
+impl Drop for WorkerPool {
+    // Workers are not joined, a hung task must not block dropping the suite
+    fn drop(&mut self) {
+        self.shared.lock().closed = true;
+        self.shared.job_available.notify_all();
+    }
+}
+
+fn worker(shared: Arc<Shared>) {
+    loop {
+        let mut queue = shared.lock();
+        let (job, status) = loop {
+            if let Some(next) = queue.jobs.pop_front() {
+                break next;
+            }
+            if queue.closed {
+                return;
+            }
+            queue = shared.job_available.wait(queue).unwrap_or_else(|e| e.into_inner());
+        };
+        drop(queue);
+        shared.space_available.notify_one();
+
+        status.set(JobState::Running);
+        match panic::catch_unwind(AssertUnwindSafe(job)) {
+            Ok(_) => status.set(JobState::Finished),
+            Err(_) => status.set(JobState::Panicked),
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use crate::errors::TaskError;
+
+pub mod simulated;
+pub(crate) mod safe_state;
+#[cfg(feature = "rppal")]
+pub mod rppal_backend;
+
+pub use simulated::SimulatedGpio;
+#[cfg(feature = "rppal")]
+pub use rppal_backend::RppalGpio;
+
+
+/// Called with the level of the pin (true for high) after an edge.
+pub type InterruptCallback = Box<dyn FnMut(bool) + Send>;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PullMode {
+    Up,
+    Down,
+    None,
+}
+
+/// Source of GPIO pins used by the environment. Implementations hand out
+/// pin objects which are owned by the pin handlers afterwards.
+pub trait GpioBackend: Send + Sync + Debug {
+    fn open_input(&self, pin: u8, pull: PullMode) -> Result<Box<dyn GpioInputPin>, TaskError>;
+    fn open_output(&self, pin: u8) -> Result<Box<dyn GpioOutputPin>, TaskError>;
+}
+
+pub trait GpioInputPin: Send + Sync + Debug {
+    fn pin(&self) -> u8;
+    fn is_high(&self) -> bool;
+    fn is_low(&self) -> bool {
+        !self.is_high()
+    }
+    /// Calls `callback` on every edge of the pin. Backends without interrupt
+    /// support keep the default, such pins are only polled.
+    fn set_interrupt(&mut self, _callback: InterruptCallback) -> Result<(), TaskError> {
+        Err(TaskError::IoError { comment: format!("Pin {} does not support interrupts", self.pin()) })
+    }
+}
+
+pub trait GpioOutputPin: Send + Sync + Debug {
+    fn pin(&self) -> u8;
+    fn write(&mut self, high: bool);
+    fn is_set_high(&self) -> bool;
+    fn set_high(&mut self) {
+        self.write(true);
+    }
+    fn set_low(&mut self) {
+        self.write(false);
+    }
+}
+
+/// Used when no backend was configured and no default backend is compiled in.
+#[derive(Debug)]
+pub struct NoGpio;
+
+impl GpioBackend for NoGpio {
+    fn open_input(&self, pin: u8, _: PullMode) -> Result<Box<dyn GpioInputPin>, TaskError> {
+        Err(TaskError::IoError { comment: format!("Could not get pin {}: no GPIO backend available", pin) })
+    }
+    fn open_output(&self, pin: u8) -> Result<Box<dyn GpioOutputPin>, TaskError> {
+        Err(TaskError::IoError { comment: format!("Could not get pin {}: no GPIO backend available", pin) })
+    }
+}
+
+#[cfg(feature = "rppal")]
+pub fn default_backend() -> Arc<dyn GpioBackend> {
+    Arc::new(RppalGpio)
+}
+
+#[cfg(not(feature = "rppal"))]
+pub fn default_backend() -> Arc<dyn GpioBackend> {
+    Arc::new(NoGpio)
+}
@@ -0,0 +1,61 @@
+use rppal::gpio::{Event, Gpio, InputPin, OutputPin, Trigger};
+
+use super::*;
+
+
+#[derive(Debug)]
+pub struct RppalGpio;
+
+impl RppalGpio {
+    fn get(pin: u8) -> Result<rppal::gpio::Pin, TaskError> {
+        Gpio::new()
+            .map_err(|e| TaskError::IoError { comment: format!("Could not access GPIOs: {}", e) })?
+            .get(pin)
+            .map_err(|e| TaskError::IoError { comment: format!("Could not get pin {}: {}", pin, e) })
+    }
+}
+
+impl GpioBackend for RppalGpio {
+    fn open_input(&self, pin: u8, pull: PullMode) -> Result<Box<dyn GpioInputPin>, TaskError> {
+        let pin = RppalGpio::get(pin)?;
+        Ok(Box::new(match pull {
+            PullMode::Up => pin.into_input_pullup(),
+            PullMode::Down => pin.into_input_pulldown(),
+            PullMode::None => pin.into_input(),
+        }))
+    }
+
+    fn open_output(&self, pin: u8) -> Result<Box<dyn GpioOutputPin>, TaskError> {
+        Ok(Box::new(RppalGpio::get(pin)?.into_output()))
+    }
+}
+
+impl GpioInputPin for InputPin {
+    fn pin(&self) -> u8 {
+        InputPin::pin(self)
+    }
+    fn is_high(&self) -> bool {
+        InputPin::is_high(self)
+    }
+    fn set_interrupt(&mut self, mut callback: InterruptCallback) -> Result<(), TaskError> {
+        let pin = InputPin::pin(self);
+        self.set_async_interrupt(Trigger::Both, None, move |event: Event| callback(event.trigger == Trigger::RisingEdge))
+            .map_err(|e| TaskError::IoError { comment: format!("Could not set interrupt on pin {}: {}", pin, e) })
+    }
+}
+
+impl GpioOutputPin for OutputPin {
+    fn pin(&self) -> u8 {
+        OutputPin::pin(self)
+    }
+    fn write(&mut self, high: bool) {
+        if high {
+            OutputPin::set_high(self);
+        } else {
+            OutputPin::set_low(self);
+        }
+    }
+    fn is_set_high(&self) -> bool {
+        OutputPin::is_set_high(self)
+    }
+}
@@ -0,0 +1,66 @@
+use std::panic;
+use std::sync::{Mutex, MutexGuard, Weak};
+
+use super::*;
+
+
+pub(crate) type SharedOutputPin = Arc<Mutex<Box<dyn GpioOutputPin>>>;
+
+pub(crate) fn lock_pin(pin: &SharedOutputPin) -> MutexGuard<'_, Box<dyn GpioOutputPin>> {
+    pin.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[derive(Debug)]
+struct SafeOutput {
+    high: bool, // electrical level of the safe state
+    pin: SharedOutputPin,
+}
+
+/// Output pins with a declared safe state. Shared between the environment
+/// and the panic hook, which must not depend on the environment lock.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct SafeOutputs {
+    outputs: Arc<Mutex<Vec<SafeOutput>>>,
+}
+
+impl SafeOutputs {
+    pub(crate) fn register(&self, high: bool, pin: SharedOutputPin) {
+        self.outputs.lock().unwrap_or_else(|e| e.into_inner())
+            .push(SafeOutput { high, pin });
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.outputs.lock().unwrap_or_else(|e| e.into_inner()).is_empty()
+    }
+
+    /// Writes the safe level to every registered pin. Pins which are locked
+    /// by someone else are skipped instead of waiting, as this also runs
+    /// while panicking.
+    pub(crate) fn drive(&self) {
+        let outputs = match self.outputs.try_lock() {
+            Ok(outputs) => outputs,
+            Err(std::sync::TryLockError::Poisoned(e)) => e.into_inner(),
+            Err(std::sync::TryLockError::WouldBlock) => return,
+        };
+        for output in outputs.iter() {
+            match output.pin.try_lock() {
+                Ok(mut pin) => pin.write(output.high),
+                Err(std::sync::TryLockError::Poisoned(e)) => e.into_inner().write(output.high),
+                Err(std::sync::TryLockError::WouldBlock) => {},
+            }
+        }
+    }
+
+    /// Chains a panic hook which drives all safe states before the
+    /// previously installed hook runs.
+    pub(crate) fn install_panic_hook(&self) {
+        let outputs: Weak<Mutex<Vec<SafeOutput>>> = Arc::downgrade(&self.outputs);
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if let Some(outputs) = outputs.upgrade() {
+                SafeOutputs { outputs }.drive();
+            }
+            previous(info);
+        }));
+    }
+}
@@ -0,0 +1,309 @@
+use core::fmt;
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+
+use crate::clock::{Clock, SystemClock};
+use super::*;
+
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct OutputWrite {
+    pub pin: u8,
+    pub high: bool,
+    pub at: f64, // seconds since the backend was created
+}
+
+struct SimulatedState {
+    clock: Arc<dyn Clock>,
+    started_at: f64,
+    inputs: HashMap<u8, bool>,
+    pulls: HashMap<u8, PullMode>,
+    scheduled: Vec<(f64, u8, bool)>, // kept sorted by time
+    outputs: HashMap<u8, bool>,
+    output_writes: Vec<OutputWrite>,
+    interrupts: HashMap<u8, InterruptCallback>,
+}
+
+impl fmt::Debug for SimulatedState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SimulatedState")
+            .field("inputs", &self.inputs)
+            .field("scheduled", &self.scheduled)
+            .field("outputs", &self.outputs)
+            .field("interrupts", &self.interrupts.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl SimulatedState {
+    fn elapsed(&self) -> f64 {
+        self.clock.now() - self.started_at
+    }
+
+    fn apply_scheduled(&mut self) {
+        let now = self.elapsed();
+        let due = self.scheduled.iter().take_while(|(at, _, _)| *at <= now).count();
+        let changes: Vec<_> = self.scheduled.drain(..due).collect();
+        for (_, pin, high) in changes {
+            self.change_input(pin, high);
+        }
+    }
+
+    /// Interrupt callbacks run while the backend is locked and must not access it.
+    fn change_input(&mut self, pin: u8, high: bool) {
+        let before = self.input_level_now(pin);
+        self.inputs.insert(pin, high);
+        if before != high {
+            if let Some(callback) = self.interrupts.get_mut(&pin) {
+                callback(high);
+            }
+        }
+    }
+
+    fn input_level_now(&self, pin: u8) -> bool {
+        match self.inputs.get(&pin) {
+            Some(high) => *high,
+            None => self.pulls.get(&pin) == Some(&PullMode::Up),
+        }
+    }
+
+    fn input_level(&mut self, pin: u8) -> bool {
+        self.apply_scheduled();
+        self.input_level_now(pin)
+    }
+}
+
+/// In-memory GPIO backend. Input levels are driven from code or a script,
+/// output writes are recorded with the time they happened at.
+/// Clones share the same pins, so one copy can be handed to the suite
+/// while another one is kept to drive and inspect it.
+#[derive(Clone, Debug)]
+pub struct SimulatedGpio {
+    state: Arc<Mutex<SimulatedState>>,
+}
+
+impl Default for SimulatedGpio {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SimulatedGpio {
+    pub fn new() -> SimulatedGpio {
+        SimulatedGpio::with_clock(Arc::new(SystemClock))
+    }
+
+    /// Uses `clock` as time base, pass the clock of the suite to drive
+    /// scripts with virtual time.
+    pub fn with_clock(clock: Arc<dyn Clock>) -> SimulatedGpio {
+        SimulatedGpio {
+            state: Arc::new(Mutex::new(SimulatedState {
+                started_at: clock.now(),
+                clock,
+                inputs: HashMap::new(),
+                pulls: HashMap::new(),
+                scheduled: Vec::new(),
+                outputs: HashMap::new(),
+                output_writes: Vec::new(),
+                interrupts: HashMap::new(),
+            })),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, SimulatedState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn set_input(&self, pin: u8, high: bool) {
+        self.lock().change_input(pin, high);
+    }
+
+    /// Applies scheduled input changes which are due, firing their interrupts.
+    /// Reading an input does this as well.
+    pub fn update(&self) {
+        self.lock().apply_scheduled();
+    }
+
+    /// Changes the level of an input pin `at` seconds after the backend was created.
+    pub fn schedule_input(&self, pin: u8, at: f64, high: bool) {
+        let mut state = self.lock();
+        let index = state.scheduled.partition_point(|(time, _, _)| *time <= at);
+        state.scheduled.insert(index, (at, pin, high));
+    }
+
+    /// Schedules input changes from a script. Every statement has the form
+    /// `pin 17 low at t=0.5s`, statements are separated by newlines, `;` or `,`.
+    /// `pin`, `at`, `t=` and `s` are optional and a statement without a pin
+    /// number reuses the pin of the one before it, so
+    /// `pin 17 low at t=0.5s, high at t=1.2s` is a valid script.
+    /// Lines starting with `#` are ignored.
+    pub fn load_script(&self, script: &str) -> Result<(), TaskError> {
+        let mut pin: Option<u8> = None;
+        let mut parsed = Vec::new();
+        for statement in script.lines()
+            .filter(|line| !line.trim_start().starts_with('#'))
+            .flat_map(|line| line.split([';', ',']))
+            .map(str::trim)
+            .filter(|statement| !statement.is_empty()) {
+            let invalid = || TaskError::SystemError { comment: format!("Invalid GPIO script statement: '{}'", statement) };
+            let mut level = None;
+            let mut at = None;
+            for token in statement.split_whitespace() {
+                match token.to_lowercase().as_str() {
+                    "pin" | "at" => {},
+                    "high" if level.is_none() && pin.is_some() => level = Some(true),
+                    "low" if level.is_none() && pin.is_some() => level = Some(false),
+                    token if level.is_none() => pin = Some(token.parse().map_err(|_| invalid())?),
+                    token if at.is_none() => {
+                        let time = token.trim_start_matches("t=").trim_end_matches('s');
+                        at = Some(time.parse::<f64>().map_err(|_| invalid())?);
+                    },
+                    _ => return Err(invalid()),
+                }
+            }
+            match (pin, level, at) {
+                (Some(pin), Some(high), Some(at)) => parsed.push((pin, at, high)),
+                _ => return Err(invalid()),
+            }
+        }
+        for (pin, at, high) in parsed {
+            self.schedule_input(pin, at, high);
+        }
+        Ok(())
+    }
+
+    pub fn input_level(&self, pin: u8) -> bool {
+        self.lock().input_level(pin)
+    }
+
+    pub fn output_level(&self, pin: u8) -> Option<bool> {
+        self.lock().outputs.get(&pin).copied()
+    }
+
+    pub fn output_writes(&self) -> Vec<OutputWrite> {
+        self.lock().output_writes.clone()
+    }
+
+    pub fn output_writes_for(&self, pin: u8) -> Vec<OutputWrite> {
+        self.lock().output_writes.iter().filter(|write| write.pin == pin).copied().collect()
+    }
+
+    pub fn clear_output_writes(&self) {
+        self.lock().output_writes.clear();
+    }
+
+    /// Seconds since the backend was created, the time base of scripts and recorded writes.
+    pub fn elapsed(&self) -> f64 {
+        self.lock().elapsed()
+    }
+}
+
+impl GpioBackend for SimulatedGpio {
+    fn open_input(&self, pin: u8, pull: PullMode) -> Result<Box<dyn GpioInputPin>, TaskError> {
+        self.lock().pulls.insert(pin, pull);
+        Ok(Box::new(SimulatedInputPin { pin, gpio: self.clone() }))
+    }
+
+    fn open_output(&self, pin: u8) -> Result<Box<dyn GpioOutputPin>, TaskError> {
+        self.lock().outputs.insert(pin, false);
+        Ok(Box::new(SimulatedOutputPin { pin, gpio: self.clone() }))
+    }
+}
+
+#[derive(Debug)]
+pub struct SimulatedInputPin {
+    pin: u8,
+    gpio: SimulatedGpio,
+}
+
+impl GpioInputPin for SimulatedInputPin {
+    fn pin(&self) -> u8 {
+        self.pin
+    }
+    fn is_high(&self) -> bool {
+        self.gpio.input_level(self.pin)
+    }
+    fn set_interrupt(&mut self, callback: InterruptCallback) -> Result<(), TaskError> {
+        self.gpio.lock().interrupts.insert(self.pin, callback);
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct SimulatedOutputPin {
+    pin: u8,
+    gpio: SimulatedGpio,
+}
+
+impl GpioOutputPin for SimulatedOutputPin {
+    fn pin(&self) -> u8 {
+        self.pin
+    }
+    fn write(&mut self, high: bool) {
+        let mut state = self.gpio.lock();
+        let at = state.elapsed();
+        state.outputs.insert(self.pin, high);
+        state.output_writes.push(OutputWrite { pin: self.pin, high, at });
+    }
+    fn is_set_high(&self) -> bool {
+        self.gpio.output_level(self.pin).unwrap_or(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+
+    fn scheduled(gpio: &SimulatedGpio) -> Vec<(f64, u8, bool)> {
+        gpio.lock().scheduled.clone()
+    }
+
+    #[test]
+    fn script_statements_and_pin_reuse() {
+        let gpio = SimulatedGpio::with_clock(Arc::new(ManualClock::new()));
+        gpio.load_script("pin 17 low at t=0.5s, high at t=1.2s; 4 HIGH 2\n# comment\n\npin 4 low at 3s").unwrap();
+        assert_eq!(scheduled(&gpio), vec![(0.5, 17, false), (1.2, 17, true), (2., 4, true), (3., 4, false)]);
+    }
+
+    #[test]
+    fn script_errors_schedule_nothing() {
+        let gpio = SimulatedGpio::with_clock(Arc::new(ManualClock::new()));
+        for invalid in ["high at 1", "pin 17 low", "pin 17 up at 1", "pin x low at 1", "pin 17 low at soon", "pin 17 low at 1 2"] {
+            assert!(gpio.load_script(invalid).is_err(), "{} should be invalid", invalid);
+        }
+        assert!(gpio.load_script("pin 17 low at 1, pin 18 at 2").is_err());
+        assert!(scheduled(&gpio).is_empty());
+    }
+
+    #[test]
+    fn script_follows_the_clock_and_fires_interrupts() {
+        let clock = ManualClock::starting_at(100.);
+        let gpio = SimulatedGpio::with_clock(Arc::new(clock.clone()));
+        let mut pin = gpio.open_input(17, PullMode::Up).unwrap();
+        let edges = Arc::new(Mutex::new(Vec::new()));
+        let recorded = edges.clone();
+        pin.set_interrupt(Box::new(move |high| recorded.lock().unwrap().push(high))).unwrap();
+        gpio.load_script("pin 17 low at 0.5, high at 0.6, high at 0.7").unwrap();
+
+        assert!(pin.is_high());
+        clock.advance(0.55);
+        assert!(pin.is_low());
+        clock.advance(1.);
+        assert!(pin.is_high());
+        // Setting the same level again is no edge
+        assert_eq!(*edges.lock().unwrap(), vec![false, true]);
+    }
+
+    #[test]
+    fn outputs_are_recorded() {
+        let clock = ManualClock::new();
+        let gpio = SimulatedGpio::with_clock(Arc::new(clock.clone()));
+        let mut pin = gpio.open_output(5).unwrap();
+        pin.write(true);
+        clock.advance(2.);
+        pin.write(false);
+        assert_eq!(gpio.output_level(5), Some(false));
+        assert_eq!(gpio.output_writes_for(5), vec![OutputWrite { pin: 5, high: true, at: 0. }, OutputWrite { pin: 5, high: false, at: 2. }]);
+    }
+}
@@ -120,19 +120,19 @@ impl LCDdriver {
         Ok(driver)
     }
     fn connect(socket_path: &Path) -> Result<UnixStream, LCDError> {
-        return UnixStream::connect(socket_path)
-            .map_err(|_| LCDError::DriverError { comment: "Could not reconnect to driver!" });
+        UnixStream::connect(socket_path)
+            .map_err(|_| LCDError::DriverError { comment: "Could not reconnect to driver!" })
     }
 
     pub fn exec(&mut self, command: LCDcommand) -> Result<(), LCDError> {
         let mut json_command = to_vec(&json!(command))
             .map_err(|_| LCDError::DriverError { comment: "Serialization failed" })?;
-        json_command.push('\n' as u8);
+        json_command.push(b'\n');
         for _ in 0..2 {
             let result = self.driver_stream
                 .write_all(&json_command)
                 .map_err(|e| {let msg = format!("Could not write to socket {:?}", e.to_string()); 
-                    return LCDError::DriverError { comment: Box::leak(msg.into_boxed_str()) }});
+                    LCDError::DriverError { comment: Box::leak(msg.into_boxed_str()) }});
             if let Ok(Some(err)) = self.driver_stream.take_error() {
                 let msg = format!("Unrecoverable Socket error: {:?}", err);
                 return Err(LCDError::DriverError { comment: Box::leak(msg.into_boxed_str()) });
@@ -145,7 +145,7 @@ impl LCDdriver {
         }
         self.driver_stream.flush()
                 .map_err(|e| {let msg = format!("Could not flush socket {:?}", e.to_string()); 
-                    return LCDError::DriverError { comment: Box::leak(msg.into_boxed_str()) }})
+                    LCDError::DriverError { comment: Box::leak(msg.into_boxed_str()) }})
         
     }
 }
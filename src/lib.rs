@@ -8,6 +8,10 @@ pub mod conditions;
 pub mod tasks;
 pub mod prebuilds;
 pub mod types;
+pub mod gpio;
+pub mod clock;
+#[cfg(test)]
+mod testing;
 
 #[macro_export]
 macro_rules! unix_now{
@@ -2,6 +2,8 @@ use std::sync::{RwLock, Arc};
 use std::collections::HashMap;
 
 use crate::errors::TaskError;
+use crate::conditions::AllwaysTrue;
+use crate::tasks::general_task::Task;
 use crate::evaluator::enviorment::{Environment};
 use crate::lcd_driver::{LCDcommand, LCDArg, LCDProgramm, LCDdriver};
 
@@ -39,27 +41,27 @@ pub fn prepare_lcd (environment: Arc<RwLock<Environment>>, msg: Option<&str>, cl
     Ok(())
 }
 
-#[macro_export]
-macro_rules! lcd_prepare{
-    ($name:expr_2021, $msg:expr_2021, $clear_lcd:expr_2021) => {(||{ 
-        use embedded_task_dispatcher::prebuilds::prepare_lcd;
-        return Task::new($name)
+pub fn lcd_prepare_task(name: &'static str, msg: Option<&str>, clear_lcd: Option<bool>) -> Task {
+    let msg = msg.map(str::to_string);
+    Task::new(name)
         .when_condition(AllwaysTrue::new().on_flank())
         .with_action(move |environment| {
-            prepare_lcd(environment, $msg, $clear_lcd)
+            prepare_lcd(environment, msg.as_deref(), clear_lcd)
         })
-        .to_eveluatable()
-        })()};
-    ($name:expr_2021, $msg:expr_2021, $clear_lcd:expr_2021, $extra_condition:expr_2021) => {(||{ 
-            use embedded_task_dispatcher::prebuilds::prepare_lcd;
-            return Task::new("test")
+}
+
+#[macro_export]
+macro_rules! lcd_prepare{
+    ($name:expr_2021, $msg:expr_2021, $clear_lcd:expr_2021) => {
+        $crate::prebuilds::lcd_prepare_task($name, $msg, $clear_lcd).to_eveluatable()
+    };
+    ($name:expr_2021, $msg:expr_2021, $clear_lcd:expr_2021, $extra_condition:expr_2021) => {{
+        use $crate::conditions::{AllwaysTrue, Gates};
+        $crate::prebuilds::lcd_prepare_task($name, $msg, $clear_lcd)
             .when_condition(Gates::and()
                 .condition(AllwaysTrue::new().on_flank())
                 .condition($extra_condition)
             )
-            .with_action(move |environment| {
-                prepare_lcd(environment, $msg, $clear_lcd)
-            })
             .to_eveluatable()
-            })()};
+    }};
 } 
\ No newline at end of file
@@ -6,6 +6,11 @@ use std::sync::Arc;
 use std::path::PathBuf;
 use std::fs;
 use std::io::Write;
+use std::time::Duration;
+#[cfg(feature = "async")]
+use std::future::Future;
+#[cfg(feature = "async")]
+use std::pin::Pin;
 
 use crate::conditions::constants::AllwaysTrue;
 use crate::evaluator::logger::LogLevel;
@@ -13,26 +18,138 @@ use crate::tasks::{Conditional, ConditionalTypes};
 use crate::tasks::TaskError;
 use crate::evaluator::enviorment::Environment;
 use crate::conditions::Condition;
+use crate::tasks::cancellation::CancellationToken;
+use crate::tasks::retry::RetryPolicy;
+use crate::types::StateType;
 
 
+pub type TaskAction = Arc<dyn Fn(Arc<RwLock<Environment>>, CancellationToken) -> Result<(), TaskError> + Send + Sync>;
+#[cfg(feature = "async")]
+pub type AsyncTaskAction = Arc<dyn Fn(Arc<RwLock<Environment>>, CancellationToken) -> Pin<Box<dyn Future<Output = Result<(), TaskError>> + Send>> + Send + Sync>;
+
+enum Attempt {
+    Done(Result<(), TaskError>),
+    RetryAt(TaskError, f64),
+}
+
 pub struct Task {
     name: &'static str,
     conditions: Option<Box<dyn Condition>>,
-    action: fn(Arc<RwLock<Environment>>) -> Result<(), TaskError>,
+    action: TaskAction,
     min_delay_between_exec: f64,
+    timeout: Option<f64>,
+    retry: Option<RetryPolicy>,
+    #[cfg(feature = "async")]
+    async_action: Option<AsyncTaskAction>,
 }
 
 impl Task {
     pub(crate) fn action(&self, environment: Arc<RwLock<Environment>>) -> Result<(), TaskError> {
-        (self.action)(environment)
+        self.run(environment, CancellationToken::new())
+    }
+
+    /// Runs the action, retrying it according to the retry policy.
+    pub(crate) fn run(&self, environment: Arc<RwLock<Environment>>, token: CancellationToken) -> Result<(), TaskError> {
+        #[cfg(feature = "async")]
+        if self.async_action.is_some() {
+            // Outside of the async dispatcher async actions get a runtime of their own,
+            // on a separate thread as runtimes can not be nested
+            return std::thread::scope(|scope| scope.spawn(|| {
+                let runtime = tokio::runtime::Builder::new_current_thread().enable_time().build()
+                    .map_err(|e| TaskError::SystemError { comment: format!("Could not start async runtime: {}", e) })?;
+                runtime.block_on(self.run_async(environment, token))
+            }).join().unwrap_or_else(|panic| std::panic::resume_unwind(panic)));
+        }
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let result = (self.action)(environment.clone(), token.clone());
+            let (error, retry_at) = match self.record_attempt(&environment, attempt, result, &token) {
+                Attempt::Done(result) => return result,
+                Attempt::RetryAt(error, retry_at) => (error, retry_at),
+            };
+            let clock = environment.read().unwrap().clock();
+            // Waits in real time, sleeping on the clock would advance a ManualClock
+            // from the task thread. Short steps notice cancellation and clock jumps.
+            while !token.is_cancelled() && clock.now() < retry_at {
+                std::thread::sleep(Duration::from_secs_f64((retry_at - clock.now()).clamp(0., 0.05)));
+            }
+            if token.is_cancelled() {
+                return Err(error);
+            }
+        }
+    }
+
+    /// Async counterpart of `run`, waits between retries without blocking the runtime.
+    #[cfg(feature = "async")]
+    pub(crate) async fn run_async(&self, environment: Arc<RwLock<Environment>>, token: CancellationToken) -> Result<(), TaskError> {
+        let action = match &self.async_action {
+            Some(action) => action,
+            None => return self.run(environment, token),
+        };
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let result = action(environment.clone(), token.clone()).await;
+            let (error, retry_at) = match self.record_attempt(&environment, attempt, result, &token) {
+                Attempt::Done(result) => return result,
+                Attempt::RetryAt(error, retry_at) => (error, retry_at),
+            };
+            let clock = environment.read().unwrap().clock();
+            while !token.is_cancelled() && clock.now() < retry_at {
+                tokio::time::sleep(Duration::from_secs_f64((retry_at - clock.now()).clamp(0., 0.05))).await;
+            }
+            if token.is_cancelled() {
+                return Err(error);
+            }
+        }
+    }
+
+    /// Keeps track of the attempts in app_state and decides whether a
+    /// failed attempt is retried and at which time.
+    fn record_attempt(&self, environment: &Arc<RwLock<Environment>>, attempt: u32, result: Result<(), TaskError>, token: &CancellationToken) -> Attempt {
+        let policy = match &self.retry {
+            Some(policy) => policy,
+            None => return Attempt::Done(result),
+        };
+        let mut env = environment.write().unwrap();
+        // Both keys are declared when the environment is initialized, the types always match
+        let _ = env.app_state.set(&format!("{}_attempts", self.name), StateType::Int(attempt as i64));
+        let _ = env.app_state.set(&format!("{}_last_error", self.name),
+            StateType::Str(result.as_ref().err().map(|e| e.to_string()).unwrap_or_default()));
+        let error = match result {
+            Err(error) if attempt < policy.max_attempts && policy.is_retryable(&error) && !token.is_cancelled() => error,
+            result => return Attempt::Done(result),
+        };
+        let delay = policy.delay_after(attempt);
+        env.log(&format!("Task {} failed (attempt {}/{}): {}, retrying in {}s",
+            self.name, attempt, policy.max_attempts, error, delay), LogLevel::Warning);
+        Attempt::RetryAt(error, env.now() + delay)
+    }
+
+    #[cfg(feature = "async")]
+    pub(crate) fn is_async(&self) -> bool {
+        self.async_action.is_some()
+    }
+
+    pub(crate) fn retry_policy(&self) -> Option<&RetryPolicy> {
+        self.retry.as_ref()
+    }
+
+    pub(crate) fn timeout(&self) -> Option<f64> {
+        self.timeout
     }
 
     pub fn new (name: &'static str) -> Task {
         Task {
             name,
             conditions: None,
-            action: move |_| {Err(TaskError::ActionError { comment: "No action provided".to_string() })},
+            action: Arc::new(move |_, _| {Err(TaskError::ActionError { comment: "No action provided".to_string() })}),
             min_delay_between_exec: 0.,
+            timeout: None,
+            retry: None,
+            #[cfg(feature = "async")]
+            async_action: None,
         }
     }
 
@@ -40,8 +157,42 @@ impl Task {
         self.conditions = Some(conditions);
         self
     }
-    pub fn with_action(mut self, action: fn(Arc<RwLock<Environment>>) -> Result<(), TaskError>) -> Task {
-        self.action = action;
+    /// Accepts plain functions as well as closures capturing their configuration.
+    pub fn with_action<F>(mut self, action: F) -> Task
+    where F: Fn(Arc<RwLock<Environment>>) -> Result<(), TaskError> + Send + Sync + 'static {
+        self.action = Arc::new(move |environment, _| action(environment));
+        self
+    }
+    /// Like `with_action`, but the action also receives a token which is
+    /// cancelled once the task exceeds its timeout or the suite shuts down.
+    pub fn with_cancellable_action<F>(mut self, action: F) -> Task
+    where F: Fn(Arc<RwLock<Environment>>, CancellationToken) -> Result<(), TaskError> + Send + Sync + 'static {
+        self.action = Arc::new(action);
+        self
+    }
+    /// Runs `action` as a tokio task under the async dispatcher, and on a
+    /// runtime of its own otherwise. Replaces any blocking action.
+    #[cfg(feature = "async")]
+    pub fn with_async_action<F, Fut>(mut self, action: F) -> Task
+    where F: Fn(Arc<RwLock<Environment>>, CancellationToken) -> Fut + Send + Sync + 'static,
+          Fut: Future<Output = Result<(), TaskError>> + Send + 'static {
+        self.async_action = Some(Arc::new(move |environment, token| Box::pin(action(environment, token))));
+        self
+    }
+    /// Seconds after which a running instance is cancelled and no longer
+    /// blocks the task from being triggered again. `{name}_timed_out` in
+    /// app_state tells whether the last run exceeded it. On a worker pool
+    /// time spent queued does not count, and a cancelled instance blocks
+    /// the task until it returns and frees its worker.
+    pub fn with_timeout(mut self, timeout: f64) -> Task {
+        self.timeout = Some(timeout);
+        self
+    }
+    /// Retries failed runs of the action. The attempts of the last run and
+    /// its last error ("" after a success) are kept in app_state as
+    /// `{name}_attempts` and `{name}_last_error`.
+    pub fn with_retry(mut self, policy: RetryPolicy) -> Task {
+        self.retry = Some(policy);
         self
     }
     pub fn with_min_delay_between_exec(mut self, min_delay_between_exec: f64) -> Task {
@@ -76,7 +227,7 @@ impl Conditional for Task {
 
 impl fmt::Debug for Task {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "GeneralTask {{ name: {}, min_delay_between_exec: {} }}", self.name, self.min_delay_between_exec)
+        write!(f, "GeneralTask {{ name: {}, min_delay_between_exec: {}, timeout: {:?} }}", self.name, self.min_delay_between_exec, self.timeout)
     }
 }
 
@@ -84,8 +235,12 @@ pub(crate) fn get_periodic_state_writer(min_delay_between_exec: u32) -> Arc<Task
     Arc::new(Task {
         name: "periodic_print_state_to_file",
         conditions: Some(AllwaysTrue::new()),
-        action: write_appstate_to_file,
+        action: Arc::new(|environment, _| write_appstate_to_file(environment)),
         min_delay_between_exec: min_delay_between_exec as f64,
+        timeout: None,
+        retry: None,
+        #[cfg(feature = "async")]
+        async_action: None,
     })
     }
 
@@ -96,13 +251,15 @@ fn write_appstate_to_file(enviorment: Arc<RwLock<Environment>>) -> Result<(), Ta
         .append(false)
         .create(true)
         .write(true)
+        .truncate(false)
         .open(state_file);
-    if file.is_err() {
-        env_reader.log("Faild to write state to statefile!", LogLevel::Error);
-    } else {
-        if let Err(e) = write!(file.unwrap(), "{}", env_reader.to_string()) {
-            env_reader.log(&format!("Failed to write to file: {}", e), LogLevel::Error);
-            return Err(TaskError::SystemError { comment: format!("Failed to write to file: {}", e) });
+    match file {
+        Err(_) => env_reader.log("Faild to write state to statefile!", LogLevel::Error),
+        Ok(mut file) => {
+            if let Err(e) = write!(file, "{}", env_reader) {
+                env_reader.log(&format!("Failed to write to file: {}", e), LogLevel::Error);
+                return Err(TaskError::SystemError { comment: format!("Failed to write to file: {}", e) });
+            }
         }
     }
     Ok(())
@@ -6,6 +6,8 @@ use std::any::Any;
 
 pub mod general_task;
 pub mod task_context;
+pub mod cancellation;
+pub mod retry;
 
 use crate::conditions::Condition;
 use crate::errors::TaskError;
@@ -14,7 +16,7 @@ use crate::evaluator::RunningTreeState;
 use crate::tasks::task_context::Unit;
 use crate::tasks::general_task::Task;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum ConditionalTypes {
     Task(Arc<Task>),
     TaskContext(Arc<Unit>),
@@ -27,10 +29,7 @@ impl ConditionalTypes {
         }
     }
     pub(crate) fn is_context(&self) -> bool {
-        match self {
-            ConditionalTypes::TaskContext(_) => true,
-            _ => false,
-        }
+        matches!(self, ConditionalTypes::TaskContext(_))
     }
 
     pub fn new_task(task: Task) -> ConditionalTypes {
@@ -46,8 +45,9 @@ pub trait Conditional {
     fn get_name(&self) -> String;
     fn min_delay_between_exec(&self) -> f64;
     fn as_any(&self) -> &dyn Any;
+    #[allow(clippy::borrowed_box)]
     fn get_conditions(&self) -> Option<&Box<dyn Condition>> {
-        return None
+        None
     }
     fn trigger(&self, environment: Arc<RwLock<Environment>>, running_tree: &RunningTreeState) -> Result<bool, TaskError> {
         let conditions = self.get_conditions();
@@ -56,14 +56,15 @@ pub trait Conditional {
         };
         Ok(false)
     }
+    #[allow(clippy::borrowed_box)]
     fn get_stay_conditions(&self) -> Option<&Box<dyn Condition>> {
-        return None
+        None
     }
     fn trigger_stay(&self, environment: Arc<RwLock<Environment>>, running_tree: &RunningTreeState) -> Result<bool, TaskError> {
         let conditions = self.get_stay_conditions();
         if let Some(conditions) = conditions {
             return conditions.eval(&environment.read().unwrap(), running_tree)
         };
-        return self.trigger(environment, running_tree);
+        self.trigger(environment, running_tree)
     }
 }
@@ -0,0 +1,46 @@
+use crate::errors::{TaskError, TaskErrorKind};
+
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Backoff {
+    Fixed(f64),
+    Exponential { initial: f64, factor: f64, max: f64 },
+}
+
+/// How often and when a failed task action is run again.
+/// Retries happen inside the running task, so it keeps blocking new
+/// triggers of the same task until it succeeded or gave up.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    pub max_attempts: u32, // including the first run
+    pub backoff: Backoff,
+    pub retry_on: Option<Vec<TaskErrorKind>>, // None retries every error
+}
+
+impl RetryPolicy {
+    pub fn fixed(max_attempts: u32, delay: f64) -> RetryPolicy {
+        RetryPolicy { max_attempts, backoff: Backoff::Fixed(delay), retry_on: None }
+    }
+
+    pub fn exponential(max_attempts: u32, initial: f64, factor: f64, max: f64) -> RetryPolicy {
+        RetryPolicy { max_attempts, backoff: Backoff::Exponential { initial, factor, max }, retry_on: None }
+    }
+
+    pub fn only_on(mut self, kinds: Vec<TaskErrorKind>) -> RetryPolicy {
+        self.retry_on = Some(kinds);
+        self
+    }
+
+    pub(crate) fn is_retryable(&self, error: &TaskError) -> bool {
+        self.retry_on.as_ref().is_none_or(|kinds| kinds.contains(&error.kind()))
+    }
+
+    /// Seconds to wait after the `attempt`th failed attempt.
+    pub(crate) fn delay_after(&self, attempt: u32) -> f64 {
+        match self.backoff {
+            Backoff::Fixed(delay) => delay,
+            Backoff::Exponential { initial, factor, max } =>
+                (initial * factor.powi(attempt.saturating_sub(1) as i32)).min(max),
+        }
+    }
+}
@@ -0,0 +1,81 @@
+//! Helpers for tests which drive a suite with a `ManualClock` and `SimulatedGpio`.
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use crate::clock::{Clock, ManualClock};
+use crate::evaluator::suite::{Suite, SutieOptions};
+use crate::gpio::SimulatedGpio;
+use crate::tasks::ConditionalTypes;
+use crate::tasks::general_task::Task;
+
+
+pub(crate) struct Simulation {
+    pub(crate) clock: ManualClock,
+    pub(crate) gpio: SimulatedGpio,
+}
+
+impl Simulation {
+    /// Clock and script time both start at 0.
+    pub(crate) fn new() -> Simulation {
+        let clock = ManualClock::starting_at(0.);
+        let gpio = SimulatedGpio::with_clock(Arc::new(clock.clone()));
+        Simulation { clock, gpio }
+    }
+
+    /// Options using the simulated clock and pins, without signal handlers.
+    pub(crate) fn options(&self) -> SutieOptions {
+        let mut options = SutieOptions::new();
+        options.gpio_backend = Some(Arc::new(self.gpio.clone()));
+        options.clock = Some(Arc::new(self.clock.clone()));
+        options.handle_signals = false;
+        options
+    }
+
+    pub(crate) fn suite<'a>(&self, tasks: Vec<(&'a str, Vec<ConditionalTypes>)>) -> Suite<'a> {
+        self.suite_with(tasks, self.options())
+    }
+
+    /// `options` should be based on `options()`.
+    pub(crate) fn suite_with<'a>(&self, tasks: Vec<(&'a str, Vec<ConditionalTypes>)>, options: SutieOptions) -> Suite<'a> {
+        Suite::new(HashMap::from_iter(tasks), None, Some(options)).unwrap()
+    }
+
+    /// Ticks at `now` and waits until the tasks it started finished.
+    pub(crate) fn tick_at(&self, suite: &mut Suite, now: f64) {
+        self.clock.set(now);
+        suite.tick().unwrap();
+        while !suite.running_tasks.is_empty() {
+            thread::sleep(Duration::from_millis(1));
+            suite.join_finished_tasks();
+        }
+    }
+
+    /// Ticks every `step` seconds after the current time up to `until`.
+    pub(crate) fn run_to(&self, suite: &mut Suite, until: f64, step: f64) {
+        let start = self.clock.now();
+        let steps = ((until - start) / step).round() as u32;
+        for step_number in 1..=steps {
+            self.tick_at(suite, start + step_number as f64 * step);
+        }
+    }
+}
+
+/// Task counting its executions in `counter`.
+pub(crate) fn counting_task(name: &'static str, counter: &Arc<AtomicU32>) -> Task {
+    let counter = counter.clone();
+    Task::new(name).with_action(move |_| {
+        counter.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    })
+}
+
+/// Path in the temporary directory which is unique to this process.
+pub(crate) fn temporary_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("embedded_task_dispatcher_{}_{}", std::process::id(), name));
+    let _ = std::fs::remove_file(&path);
+    path
+}
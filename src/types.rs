@@ -1,47 +1,167 @@
 extern crate custom_error;
 use core::fmt;
-use crate::unix_now;
-use rppal::gpio::{InputPin, OutputPin};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use serde::{Deserialize, Serialize};
 
+use crate::clock::Clock;
+use crate::gpio::{GpioInputPin, PullMode};
+use crate::gpio::safe_state::{SharedOutputPin, lock_pin};
 
-#[derive(PartialEq, Clone, Debug)]
+
+/// Value of an app_state key. Serializes to and from plain JSON values,
+/// integers which fit an i64 become `Int`, other numbers `Float`.
+#[derive(PartialEq, Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(untagged)]
 pub enum StateType {
-    Str(String),
+    #[default]
+    None,
     Bool(bool),
-    Int(f64),
+    Int(i64),
+    Float(f64),
+    Str(String),
+    List(Vec<StateType>),
+    Map(BTreeMap<String, StateType>),
 }
 impl StateType {
     pub fn as_default(&self) -> StateType {
         match self {
+            StateType::None => StateType::None,
             StateType::Str(_) => StateType::Str("".to_string()),
             StateType::Bool(_) => StateType::Bool(false),
-            StateType::Int(_) => StateType::Int(0.),
+            StateType::Int(_) => StateType::Int(0),
+            StateType::Float(_) => StateType::Float(0.),
+            StateType::List(_) => StateType::List(Vec::new()),
+            StateType::Map(_) => StateType::Map(BTreeMap::new()),
         }
     }
 
     pub fn as_bool(&self) -> bool {
         match self {
-            StateType::Str(s) => *s != "",
+            StateType::None => false,
+            StateType::Str(s) => !s.is_empty(),
             StateType::Bool(b) => *b,
-            StateType::Int(i) => *i != 0.,
+            StateType::Int(i) => *i != 0,
+            StateType::Float(f) => *f != 0.,
+            StateType::List(list) => !list.is_empty(),
+            StateType::Map(map) => !map.is_empty(),
+        }
+    }
+
+    /// Floats are truncated, strings parsed; lists, maps and None are 0.
+    pub fn as_int(&self) -> i64 {
+        match self {
+            StateType::Str(s) => s.parse::<i64>().unwrap_or_else(|_| s.parse::<f64>().unwrap_or(0.) as i64),
+            StateType::Bool(b) => *b as i64,
+            StateType::Int(i) => *i,
+            StateType::Float(f) => *f as i64,
+            StateType::None | StateType::List(_) | StateType::Map(_) => 0,
         }
     }
 
-    pub fn as_int(&self) -> f64 {
+    pub fn as_float(&self) -> f64 {
         match self {
             StateType::Str(s) => s.parse::<f64>().unwrap_or(0.),
             StateType::Bool(b) => if *b { 1. } else { 0. },
-            StateType::Int(i) => *i,
+            StateType::Int(i) => *i as f64,
+            StateType::Float(f) => *f,
+            StateType::None | StateType::List(_) | StateType::Map(_) => 0.,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            StateType::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[StateType]> {
+        match self {
+            StateType::List(list) => Some(list),
+            _ => None,
+        }
+    }
+
+    pub fn as_map(&self) -> Option<&BTreeMap<String, StateType>> {
+        match self {
+            StateType::Map(map) => Some(map),
+            _ => None,
         }
     }
+
+    pub fn is_none(&self) -> bool {
+        matches!(self, StateType::None)
+    }
+
+    /// Int or Float.
+    pub fn is_number(&self) -> bool {
+        matches!(self, StateType::Int(_) | StateType::Float(_))
+    }
+}
+
+impl From<bool> for StateType {
+    fn from(value: bool) -> Self {
+        StateType::Bool(value)
+    }
+}
+impl From<i64> for StateType {
+    fn from(value: i64) -> Self {
+        StateType::Int(value)
+    }
+}
+impl From<i32> for StateType {
+    fn from(value: i32) -> Self {
+        StateType::Int(value as i64)
+    }
+}
+impl From<u32> for StateType {
+    fn from(value: u32) -> Self {
+        StateType::Int(value as i64)
+    }
+}
+impl From<f64> for StateType {
+    fn from(value: f64) -> Self {
+        StateType::Float(value)
+    }
+}
+impl From<&str> for StateType {
+    fn from(value: &str) -> Self {
+        StateType::Str(value.to_string())
+    }
+}
+impl From<String> for StateType {
+    fn from(value: String) -> Self {
+        StateType::Str(value)
+    }
+}
+impl<T: Into<StateType>> From<Vec<T>> for StateType {
+    fn from(value: Vec<T>) -> Self {
+        StateType::List(value.into_iter().map(Into::into).collect())
+    }
+}
+impl<T: Into<StateType>> From<BTreeMap<String, T>> for StateType {
+    fn from(value: BTreeMap<String, T>) -> Self {
+        StateType::Map(value.into_iter().map(|(key, value)| (key, value.into())).collect())
+    }
+}
+impl<T: Into<StateType>> From<Option<T>> for StateType {
+    fn from(value: Option<T>) -> Self {
+        value.map(Into::into).unwrap_or(StateType::None)
+    }
 }
 
 impl fmt::Display for StateType {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
+            StateType::None => write!(f, "null"),
             StateType::Str(s) => write!(f, "{}", s),
             StateType::Bool(b) => write!(f, "{}", b),
-            StateType::Int(i) => write!(f, "{:?}", i),
+            StateType::Int(i) => write!(f, "{}", i),
+            StateType::Float(i) => write!(f, "{:?}", i),
+            StateType::List(_) | StateType::Map(_) =>
+                write!(f, "{}", serde_json::to_string(self).map_err(|_| fmt::Error)?),
         }
     }
     
@@ -52,14 +172,135 @@ pub trait PinHandler {
     fn current_state(&self) -> bool;
     fn last_state(&self) -> bool;
     fn last_change(&self) -> f64;
+    /// Dispatcher tick in which the dispatcher last saw `current_state` change.
+    fn changed_in_tick(&self) -> u64;
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct InputPinConfig {
+    pub pull: PullMode,
+    pub active_low: bool, // a low level reads as true, the default for buttons pulling against the pull-up
+    pub debounce: f64, // seconds the raw level has to be stable before current_state follows it
+    pub count_pulses: bool, // count edges to the active level into app_state, see `pulse_count_key`
+    pub pulse_rate_window: f64, // seconds over which the pulse rate is averaged
+}
+
+impl Default for InputPinConfig {
+    fn default() -> Self {
+        InputPinConfig { pull: PullMode::Up, active_low: true, debounce: 0., count_pulses: false, pulse_rate_window: 1. }
+    }
+}
+
+/// app_state key holding the number of pulses counted on `pin`, as Int.
+/// Tasks reset the counter by setting it to 0.
+pub fn pulse_count_key(pin: u8) -> String {
+    format!("gpio{}_pulses", pin)
+}
+
+/// app_state key holding the pulse rate of `pin` in pulses per second, as Float.
+pub fn pulse_rate_key(pin: u8) -> String {
+    format!("gpio{}_pulse_rate", pin)
 }
 
+/// Counts pulses of an input pin. With interrupts every edge is counted,
+/// not only the ones seen when polling. The debounce time does not apply.
+#[derive(Debug)]
+pub(crate) struct PulseCounter {
+    pub(crate) count: Arc<AtomicU64>, // total, written by the interrupt callback
+    pub(crate) interrupts: bool, // false if counted when polling
+    recorded: u64, // part of count already added to app_state
+    window_start: f64,
+    window_count: u64,
+    pub(crate) rate: f64,
+}
+
+impl PulseCounter {
+    pub(crate) fn new(now: f64) -> PulseCounter {
+        PulseCounter { count: Arc::new(AtomicU64::new(0)), interrupts: false, recorded: 0, window_start: now, window_count: 0, rate: 0. }
+    }
+
+    /// Pulses counted since the last call.
+    pub(crate) fn take_new(&mut self) -> u64 {
+        let count = self.count.load(Ordering::Relaxed);
+        let new = count - self.recorded;
+        self.recorded = count;
+        new
+    }
+
+    fn update_rate(&mut self, now: f64, window: f64) {
+        let elapsed = now - self.window_start;
+        if elapsed >= window && elapsed > 0. {
+            let count = self.count.load(Ordering::Relaxed);
+            self.rate = (count - self.window_count) as f64 / elapsed;
+            self.window_start = now;
+            self.window_count = count;
+        }
+    }
+}
+
+/// A change of the debounced level of an input pin.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Edge {
+    pub id: u64, // counts up from 1 for every edge of the pin
+    pub at: f64,
+    pub state: bool, // level after the edge, true for active
+}
+
+const EDGE_HISTORY: usize = 64;
+
 #[derive(Debug)]
 pub struct InputPinHandler {
-    pub(crate) handler: InputPin,
-    pub current_state: bool,
+    pub(crate) handler: Box<dyn GpioInputPin>,
+    pub(crate) config: InputPinConfig,
+    pub(crate) edges: VecDeque<Edge>, // most recent last
+    pub current_state: bool, // debounced
     pub last_state: bool,
     pub last_change: f64,
+    pub raw_state: bool,
+    pub raw_change: f64,
+    pub(crate) changed_in_tick: u64,
+    pub(crate) pulses: Option<PulseCounter>,
+}
+
+impl InputPinHandler {
+    /// Samples the pin, `current_state` only follows once the raw level
+    /// was stable for the debounce time.
+    pub(crate) fn refresh(&mut self, now: f64, tick: u64) {
+        let raw_state = self.handler.is_high() != self.config.active_low;
+        let raw_change_now = raw_state != self.raw_state;
+        if raw_change_now {
+            self.raw_state = raw_state;
+            self.raw_change = now;
+        }
+        self.last_state = self.current_state;
+        if self.raw_state != self.current_state && now - self.raw_change >= self.config.debounce {
+            self.current_state = self.raw_state;
+            self.last_change = now;
+            self.changed_in_tick = tick;
+            let id = self.edges.back().map_or(1, |edge| edge.id + 1);
+            if self.edges.len() == EDGE_HISTORY {
+                self.edges.pop_front();
+            }
+            self.edges.push_back(Edge { id, at: now, state: self.current_state });
+        }
+        if let Some(counter) = self.pulses.as_mut() {
+            if !counter.interrupts && self.raw_state && raw_change_now {
+                counter.count.fetch_add(1, Ordering::Relaxed);
+            }
+            counter.update_rate(now, self.config.pulse_rate_window);
+        }
+    }
+
+    /// Recent edges of the debounced level, oldest first.
+    pub fn edges(&self) -> impl DoubleEndedIterator<Item = &Edge> {
+        self.edges.iter()
+    }
+
+    /// Seconds until a pending raw change settles, None if there is none.
+    pub(crate) fn settles_in(&self, now: f64) -> Option<f64> {
+        (self.raw_state != self.current_state)
+            .then(|| (self.raw_change + self.config.debounce - now).max(0.))
+    }
 }
 
 impl PinHandler for InputPinHandler {
@@ -74,30 +315,57 @@ impl PinHandler for InputPinHandler {
     fn last_change(&self) -> f64 {
         self.last_change
     }
-    
+
+    fn changed_in_tick(&self) -> u64 {
+        self.changed_in_tick
+    }
 }
 
 impl fmt::Display for InputPinHandler {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "Pin: {}, Current state: {}, Last state: {}, Last change: {}",
+            "Pin: {}, Current state: {}, Last state: {}, Last change: {}, Raw state: {}",
             self.handler.pin(),
             self.current_state,
             self.last_state,
-            self.last_change
+            self.last_change,
+            self.raw_state
         )
     }
     
 }
 
 
+#[derive(Clone, Copy, Debug)]
+pub struct OutputPinConfig {
+    pub safe_state: Option<bool>, // driven on shutdown and panics, same sense as OutputPinHandler::change_state
+    pub active_low: bool, // true drives the pin low, the default for relay boards
+}
+
+impl Default for OutputPinConfig {
+    fn default() -> Self {
+        OutputPinConfig { safe_state: None, active_low: true }
+    }
+}
+
+impl OutputPinConfig {
+    /// Pin level for a logical state.
+    pub(crate) fn level(&self, state: bool) -> bool {
+        state != self.active_low
+    }
+}
+
 #[derive(Debug)]
 pub struct OutputPinHandler {
-    pub(crate) handler: OutputPin,
+    pub(crate) handler: SharedOutputPin,
+    pub(crate) clock: Arc<dyn Clock>,
+    pub(crate) config: OutputPinConfig,
     pub current_state: bool,
     pub last_state: bool,
     pub last_change: f64,
+    pub(crate) sampled_state: bool, // current_state at the last tick
+    pub(crate) changed_in_tick: u64,
 }
 
 impl PinHandler for OutputPinHandler {
@@ -112,6 +380,10 @@ impl PinHandler for OutputPinHandler {
     fn last_change(&self) -> f64 {
         self.last_change
     }
+
+    fn changed_in_tick(&self) -> u64 {
+        self.changed_in_tick
+    }
 }
 
 impl OutputPinHandler{
@@ -119,12 +391,17 @@ impl OutputPinHandler{
         if self.current_state != new_state {
             self.last_state = self.current_state;
             self.current_state = new_state;
-            self.last_change = unix_now!(f64);
-            if !new_state {
-                self.handler.set_high();
-            } else {
-                self.handler.set_low();
-            }
+            self.last_change = self.clock.now();
+            lock_pin(&self.handler).write(self.config.level(new_state));
+        }
+    }
+
+    /// Tasks change outputs at any time, edges are attributed to the tick
+    /// in which the dispatcher first sees them.
+    pub(crate) fn sample(&mut self, tick: u64) {
+        if self.sampled_state != self.current_state {
+            self.sampled_state = self.current_state;
+            self.changed_in_tick = tick;
         }
     }
 }
@@ -134,11 +411,51 @@ impl fmt::Display for OutputPinHandler {
         write!(
             f,
             "Pin: {}, Current state: {}, Last state: {}, Last change: {}",
-            self.handler.pin(),
+            lock_pin(&self.handler).pin(),
             self.current_state,
             self.last_state,
             self.last_change
         )
     }
     
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicU32;
+
+    use super::*;
+    use crate::conditions::digital_gpio::DigitalGpioCondition;
+    use crate::evaluator::enviorment::Environment;
+    use crate::testing::{counting_task, Simulation};
+
+    #[test]
+    fn debounce_ignores_bouncing_contacts() {
+        let simulation = Simulation::new();
+        // Active low, the press bounces for 40ms and the release for 20ms
+        simulation.gpio.load_script("pin 17 low at 1, high at 1.01, low at 1.02, high at 1.03, low at 1.04, \
+            high at 2, low at 2.01, high at 2.02").unwrap();
+        let mut options = simulation.options();
+        options.input_pin_config.insert(17, InputPinConfig { debounce: 0.05, ..InputPinConfig::default() });
+        let presses = Arc::new(AtomicU32::new(0));
+        let mut suite = simulation.suite_with(vec![("button", vec![counting_task("pressed", &presses)
+            .when_condition(DigitalGpioCondition::new_input(17).on_flank())
+            .to_eveluatable()])], options);
+        let environment = suite.environment();
+        let pin = |environment: &Environment| {
+            let handler = &environment.input_gpios[&17];
+            (handler.raw_state, handler.current_state, handler.edges().count())
+        };
+
+        simulation.run_to(&mut suite, 1.07, 0.01);
+        assert_eq!(pin(&environment.read().unwrap()), (true, false, 0));
+        assert_eq!(presses.load(Ordering::SeqCst), 0);
+
+        simulation.run_to(&mut suite, 1.2, 0.01);
+        assert_eq!(pin(&environment.read().unwrap()), (true, true, 1));
+        assert_eq!(presses.load(Ordering::SeqCst), 1);
+
+        simulation.run_to(&mut suite, 3., 0.01);
+        assert_eq!(pin(&environment.read().unwrap()), (false, false, 2));
+        assert_eq!(presses.load(Ordering::SeqCst), 1);
+    }
+}